@@ -0,0 +1,318 @@
+use axum::{
+	extract::{ConnectInfo, FromRequestParts},
+	http::{HeaderMap, request::Parts}
+};
+#[cfg(feature = "magazines")]
+use axum::http::{header::HOST, uri::Authority};
+use std::{
+	convert::Infallible,
+	env::var,
+	fmt,
+	net::{IpAddr, Ipv4Addr, SocketAddr},
+	sync::OnceLock
+};
+
+// proxies from TRUSTED_PROXIES in the form of 10.0.0.0/8|127.0.0.1
+static TRUSTED_PROXIES: OnceLock<Vec<Cidr>> = OnceLock::new();
+
+// ip of the client which sent the request, forwarded headers are only honored from trusted proxies
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+impl fmt::Display for ClientIp {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		return self.0.fmt(f);
+	}
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+	type Rejection = Infallible;
+
+	async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+		return Ok(ClientIp(resolve(peer(parts), &parts.headers, trusted_proxies())));
+	}
+}
+
+// scheme and host the client used, X-Forwarded-Proto and X-Forwarded-Host are only honored from trusted proxies
+#[cfg(feature = "magazines")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RequestOrigin {
+	pub scheme: String,
+	pub host: String,
+}
+
+#[cfg(feature = "magazines")]
+impl fmt::Display for RequestOrigin {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		return write!(f, "{}://{}", self.scheme, self.host);
+	}
+}
+
+#[cfg(feature = "magazines")]
+impl<S: Send + Sync> FromRequestParts<S> for RequestOrigin {
+	type Rejection = Infallible;
+
+	async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+		return Ok(origin(peer(parts), &parts.headers, trusted_proxies()));
+	}
+}
+
+fn peer(parts: &Parts) -> IpAddr {
+	return parts.extensions.get::<ConnectInfo<SocketAddr>>()
+		.map(|ConnectInfo(addr)| addr.ip())
+		.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+}
+
+struct Cidr {
+	network: IpAddr,
+	prefix: u32,
+}
+
+impl Cidr {
+	fn parse(value: &str) -> Option<Cidr> {
+		let (address, prefix) = match value.split_once("/") {
+			Some((address, prefix)) => (address, Some(prefix.parse::<u32>().ok()?)),
+			None => (value, None),
+		};
+
+		let network = address.parse::<IpAddr>().ok()?.to_canonical();
+		let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+		let prefix = prefix.unwrap_or(max_prefix);
+
+		if prefix > max_prefix {
+			return None;
+		}
+
+		return Some(Cidr { network, prefix });
+	}
+
+	fn contains(&self, ip: IpAddr) -> bool {
+		return match (self.network, ip.to_canonical()) {
+			(IpAddr::V4(network), IpAddr::V4(ip)) => {
+				let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+				u32::from(network) & mask == u32::from(ip) & mask
+			},
+			(IpAddr::V6(network), IpAddr::V6(ip)) => {
+				let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+				u128::from(network) & mask == u128::from(ip) & mask
+			},
+			_ => false,
+		};
+	}
+}
+
+fn trusted_proxies() -> &'static [Cidr] {
+	return TRUSTED_PROXIES.get_or_init(|| {
+		var("TRUSTED_PROXIES").unwrap_or_default()
+			.split("|")
+			.map(|value| value.trim())
+			.filter(|value| !value.is_empty())
+			.filter_map(|value| {
+				let cidr = Cidr::parse(value);
+				if cidr.is_none() {
+					eprintln!("[ClientIp] Ignoring invalid trusted proxy {value}");
+				}
+				cidr
+			})
+			.collect()
+	});
+}
+
+fn is_trusted(trusted: &[Cidr], ip: IpAddr) -> bool {
+	return trusted.iter().any(|cidr| cidr.contains(ip));
+}
+
+fn resolve(peer: IpAddr, headers: &HeaderMap, trusted: &[Cidr]) -> IpAddr {
+	let is_trusted = |ip: &IpAddr| is_trusted(trusted, *ip);
+
+	if !is_trusted(&peer) {
+		return peer.to_canonical();
+	}
+
+	// the standard header wins over the legacy one when a proxy sets both
+	let mut hops = forwarded_hops(headers);
+	if hops.is_empty() {
+		hops = x_forwarded_for_hops(headers);
+	}
+
+	// walk from the closest hop and stop at the first address which isn't a trusted proxy
+	for ip in hops.iter().rev() {
+		if !is_trusted(ip) {
+			return ip.to_canonical();
+		}
+	}
+
+	return hops.first().copied().unwrap_or(peer).to_canonical();
+}
+
+// trusted proxies are expected to replace these headers, of a list only the first value is used
+#[cfg(feature = "magazines")]
+fn origin(peer: IpAddr, headers: &HeaderMap, trusted: &[Cidr]) -> RequestOrigin {
+	let header = |name| headers.get(name).and_then(|value| value.to_str().ok()).and_then(|value| value.split(",").next()).map(|value| value.trim());
+	let from_proxy = is_trusted(trusted, peer);
+
+	let scheme = match header("X-Forwarded-Proto").filter(|_| from_proxy).map(|value| value.to_ascii_lowercase()) {
+		Some(scheme) if scheme == "https" => "https",
+		_ => "http",
+	};
+
+	// only a plain host[:port] is accepted, anything else could inject into generated links
+	let host = header("X-Forwarded-Host").filter(|_| from_proxy)
+		.or(header(HOST.as_str()))
+		.and_then(|host| host.parse::<Authority>().ok())
+		.filter(|authority| !authority.as_str().contains("@"))
+		.map(|authority| authority.as_str().to_ascii_lowercase())
+		.unwrap_or("localhost".to_string());
+
+	return RequestOrigin { scheme: scheme.to_string(), host: host };
+}
+
+fn x_forwarded_for_hops(headers: &HeaderMap) -> Vec<IpAddr> {
+	return headers.get_all("X-Forwarded-For").iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(","))
+		.filter_map(|value| parse_node(value.trim()))
+		.collect();
+}
+
+// Forwarded: for=192.0.2.60;proto=http, for="[2001:db8::17]:4711"
+fn forwarded_hops(headers: &HeaderMap) -> Vec<IpAddr> {
+	return headers.get_all("Forwarded").iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(","))
+		.filter_map(|element| {
+			element.split(";")
+				.filter_map(|pair| pair.trim().split_once("="))
+				.find(|(key, _)| key.eq_ignore_ascii_case("for"))
+				.and_then(|(_, value)| parse_node(value.trim_matches('"')))
+		})
+		.collect();
+}
+
+fn parse_node(node: &str) -> Option<IpAddr> {
+	if let Ok(ip) = node.parse() {
+		return Some(ip);
+	}
+
+	// [ipv6]:port or [ipv6]
+	if let Some(rest) = node.strip_prefix("[") {
+		return rest.split_once("]")?.0.parse().ok();
+	}
+
+	// ipv4:port
+	let (address, _) = node.split_once(":")?;
+	return address.parse::<Ipv4Addr>().ok().map(IpAddr::V4);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		for (name, value) in pairs {
+			headers.append(*name, value.parse().unwrap());
+		}
+		return headers;
+	}
+
+	fn ip(value: &str) -> IpAddr {
+		return value.parse().unwrap();
+	}
+
+	fn proxies() -> Vec<Cidr> {
+		return ["10.0.0.0/8", "2001:db8:1::/48", "127.0.0.1"].into_iter().map(|value| Cidr::parse(value).unwrap()).collect();
+	}
+
+	#[test]
+	fn parses_cidrs() {
+		let network = Cidr::parse("10.0.0.0/8").unwrap();
+		assert!(network.contains(ip("10.255.0.1")));
+		assert!(network.contains(ip("::ffff:10.1.2.3")));
+		assert!(!network.contains(ip("11.0.0.1")));
+
+		let single = Cidr::parse("::ffff:192.0.2.1").unwrap();
+		assert_eq!(single.prefix, 32);
+		assert!(single.contains(ip("192.0.2.1")));
+		assert!(!single.contains(ip("192.0.2.2")));
+
+		assert!(Cidr::parse("2001:db8::/32").unwrap().contains(ip("2001:db8:ffff::1")));
+		assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(ip("203.0.113.9")));
+		assert!(!Cidr::parse("0.0.0.0/0").unwrap().contains(ip("2001:db8::1")));
+
+		for invalid in ["10.0.0.0/33", "::/129", "10.0.0.0/", "10.0.0.0/-1", "10.0.0/8", "example.com", ""] {
+			assert!(Cidr::parse(invalid).is_none(), "{invalid}");
+		}
+	}
+
+	#[test]
+	fn ignores_headers_from_untrusted_peers() {
+		let spoofed = headers(&[("X-Forwarded-For", "203.0.113.7"), ("Forwarded", "for=203.0.113.8")]);
+
+		assert_eq!(resolve(ip("198.51.100.1"), &spoofed, &proxies()), ip("198.51.100.1"));
+		assert_eq!(resolve(ip("::ffff:198.51.100.1"), &spoofed, &proxies()), ip("198.51.100.1"));
+	}
+
+	#[test]
+	fn stops_at_the_first_untrusted_hop() {
+		// the client prepended a fake address, the proxy appended the real one
+		let spoofed = headers(&[("X-Forwarded-For", "1.2.3.4, 203.0.113.7, 10.0.0.2")]);
+		assert_eq!(resolve(ip("10.0.0.1"), &spoofed, &proxies()), ip("203.0.113.7"));
+
+		// a chain of only trusted proxies resolves to the outermost one
+		let internal = headers(&[("X-Forwarded-For", "10.0.0.3, 10.0.0.2")]);
+		assert_eq!(resolve(ip("10.0.0.1"), &internal, &proxies()), ip("10.0.0.3"));
+
+		// repeated headers are one list
+		let repeated = headers(&[("X-Forwarded-For", "1.2.3.4"), ("X-Forwarded-For", "203.0.113.7")]);
+		assert_eq!(resolve(ip("127.0.0.1"), &repeated, &proxies()), ip("203.0.113.7"));
+
+		assert_eq!(resolve(ip("10.0.0.1"), &HeaderMap::new(), &proxies()), ip("10.0.0.1"));
+	}
+
+	#[test]
+	fn parses_forwarded_headers() {
+		let forwarded = headers(&[
+			("Forwarded", "for=192.0.2.60;proto=http;by=203.0.113.43, for=\"[2001:db8:cafe::17]:4711\""),
+			("X-Forwarded-For", "198.51.100.9"),
+		]);
+		assert_eq!(resolve(ip("10.0.0.1"), &forwarded, &proxies()), ip("2001:db8:cafe::17"));
+
+		let with_port = headers(&[("Forwarded", "For=\"192.0.2.61:8080\"")]);
+		assert_eq!(resolve(ip("10.0.0.1"), &with_port, &proxies()), ip("192.0.2.61"));
+
+		let trusted_v6 = headers(&[("Forwarded", "for=192.0.2.62, for=\"[2001:db8:1::5]\"")]);
+		assert_eq!(resolve(ip("2001:db8:1::1"), &trusted_v6, &proxies()), ip("192.0.2.62"));
+	}
+
+	#[test]
+	fn skips_malformed_entries() {
+		let malformed = headers(&[("X-Forwarded-For", "203.0.113.7, unknown, , 2001:db8::1:80x, [2001:db8:cafe::1]:443, 10.0.0.2")]);
+		assert_eq!(resolve(ip("10.0.0.1"), &malformed, &proxies()), ip("2001:db8:cafe::1"));
+
+		let obfuscated = headers(&[("Forwarded", "for=_hidden, for=unknown;proto=https, for=\"[2001:db8:cafe::2\", for=203.0.113.9")]);
+		assert_eq!(resolve(ip("10.0.0.1"), &obfuscated, &proxies()), ip("203.0.113.9"));
+
+		let nothing_valid = headers(&[("X-Forwarded-For", "unknown, 999.1.1.1")]);
+		assert_eq!(resolve(ip("10.0.0.1"), &nothing_valid, &proxies()), ip("10.0.0.1"));
+
+		assert_eq!(parse_node("2001:db8::1"), Some(ip("2001:db8::1")));
+		assert_eq!(parse_node("2001:db8::1:80x"), None);
+		assert_eq!(parse_node("192.0.2.1:"), Some(ip("192.0.2.1")));
+	}
+
+	#[test]
+	#[cfg(feature = "magazines")]
+	fn honors_forwarded_origin_only_from_trusted_proxies() {
+		let forwarded = headers(&[("Host", "backend:3000"), ("X-Forwarded-Proto", "https"), ("X-Forwarded-Host", "Example.org")]);
+
+		assert_eq!(origin(ip("10.0.0.1"), &forwarded, &proxies()).to_string(), "https://example.org");
+		assert_eq!(origin(ip("198.51.100.1"), &forwarded, &proxies()).to_string(), "http://backend:3000");
+
+		let injected = headers(&[("Host", "evil.example/\"><script>"), ("X-Forwarded-Proto", "javascript")]);
+		assert_eq!(origin(ip("10.0.0.1"), &injected, &proxies()).to_string(), "http://localhost");
+
+		let credentials = headers(&[("Host", "user@evil.example")]);
+		assert_eq!(origin(ip("198.51.100.1"), &credentials, &proxies()).host, "localhost");
+	}
+}
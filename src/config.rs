@@ -0,0 +1,65 @@
+use std::{
+	collections::HashMap,
+	env::var
+};
+
+// collects all missing env vars of a module so they can be reported at once
+#[cfg(any(feature = "workflow", feature = "infomaniakmail", feature = "startpage", feature = "plugins"))]
+pub struct RequiredEnv {
+	module: &'static str,
+	missing: Vec<&'static str>,
+}
+
+#[cfg(any(feature = "workflow", feature = "infomaniakmail", feature = "startpage", feature = "plugins"))]
+impl RequiredEnv {
+	pub fn new(module: &'static str) -> RequiredEnv {
+		return RequiredEnv { module, missing: vec![] };
+	}
+
+	pub fn get(&mut self, name: &'static str) -> String {
+		match var(name) {
+			Ok(value) if !value.is_empty() => value,
+			_ => {
+				self.missing.push(name);
+				String::new()
+			}
+		}
+	}
+
+	// returns false and logs a warning when the module can't be enabled
+	pub fn is_complete(&self) -> bool {
+		if self.missing.is_empty() {
+			return true;
+		}
+
+		eprintln!("[{}] Disabled, missing env vars: {}", self.module, self.missing.join(", "));
+		return false;
+	}
+}
+
+#[cfg(any(feature = "magazines", feature = "workflow"))]
+pub fn flag(name: &str, default: bool) -> bool {
+	match var(name) {
+		Ok(value) if value.eq_ignore_ascii_case("true") => true,
+		Ok(value) if value.eq_ignore_ascii_case("false") => false,
+		_ => default,
+	}
+}
+
+// parse maps in the form of key;value|key;value
+pub fn parse_map(value: &str) -> HashMap<String, String> {
+	return parse_list(value).into_iter().collect();
+}
+
+// same format as parse_map but keeps the order of the entries
+pub fn parse_list(value: &str) -> Vec<(String, String)> {
+	let mut list = vec![];
+
+	for entry in value.split("|") {
+		if let Some((key, value)) = entry.split_once(";") {
+			list.push((key.to_string(), value.to_string()));
+		}
+	}
+
+	return list;
+}
@@ -3,7 +3,9 @@ use axum::{
 	response::{IntoResponse,Response}
 };
 use serde_json;
+#[cfg(any(feature = "magazines", feature = "infomaniakmail", feature = "plugins"))]
 use reqwest;
+#[cfg(feature = "workflow")]
 use hex;
 
 pub fn generic_request_error(err: &str) -> Response {
@@ -13,6 +15,7 @@ pub fn generic_request_error(err: &str) -> Response {
 	return (StatusCode::BAD_REQUEST, body).into_response();
 }
 
+#[cfg(any(feature = "workflow", feature = "infomaniakmail", feature = "startpage"))]
 pub fn generic_unauthorized_error(err: &str) -> Response {
 	let body = err.to_string();
 
@@ -20,6 +23,31 @@ pub fn generic_unauthorized_error(err: &str) -> Response {
 	return (StatusCode::UNAUTHORIZED, body).into_response();
 }
 
+#[cfg(any(feature = "magazines", feature = "startpage"))]
+pub fn generic_unprocessable_error(err: &str) -> Response {
+	let body = err.to_string();
+
+	eprintln!("{body}");
+	return (StatusCode::UNPROCESSABLE_ENTITY, body).into_response();
+}
+
+#[cfg(any(feature = "magazines", feature = "workflow"))]
+pub fn generic_unavailable_error(err: &str) -> Response {
+	let body = err.to_string();
+
+	eprintln!("{body}");
+	return (StatusCode::SERVICE_UNAVAILABLE, body).into_response();
+}
+
+#[cfg(any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "startpage"))]
+pub fn generic_internal_error(err: &str) -> Response {
+	let body = err.to_string();
+
+	eprintln!("{body}");
+	return (StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+}
+
+#[cfg(any(feature = "magazines", feature = "infomaniakmail", feature = "plugins"))]
 pub fn map_reqwest_error(err: reqwest::Error, source: &str) -> Response {
 	let status = err.status().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
 	let body = err.to_string();
@@ -35,6 +63,15 @@ pub fn map_serde_error(err: serde_json::Error, source: &str) -> Response {
 	return (StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
 }
 
+#[cfg(any(feature = "magazines", feature = "infomaniakmail", feature = "startpage"))]
+pub fn map_invalid_body_error(err: serde_json::Error, source: &str) -> Response {
+	let body = err.to_string();
+
+	eprintln!("[{source}] {body}");
+	return (StatusCode::BAD_REQUEST, body).into_response();
+}
+
+#[cfg(feature = "workflow")]
 pub fn map_hex_error(err: hex::FromHexError, source: &str) -> Response {
 	let body = err.to_string();
 
@@ -0,0 +1,278 @@
+use reqwest::{
+	self,
+	IntoUrl, Method, RequestBuilder
+};
+use std::{
+	collections::HashMap,
+	env::var,
+	time::Duration
+};
+// only user supplied urls need the address checks
+#[cfg(feature = "startpage")]
+use reqwest::{
+	dns::{Addrs, Name, Resolve, Resolving},
+	redirect,
+	Url
+};
+#[cfg(feature = "startpage")]
+use std::{
+	net::{IpAddr, Ipv4Addr, ToSocketAddrs},
+	sync::Arc
+};
+
+use crate::config;
+
+// one connection pool for all upstream calls
+#[derive(Clone)]
+pub struct HttpClient {
+	#[cfg(any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "plugins"))]
+	client: reqwest::Client,
+	// for urls supplied by users, only connects to public addresses
+	#[cfg(feature = "startpage")]
+	public_client: reqwest::Client,
+	timeout: Duration,
+	overrides: HashMap<String, Duration>,
+}
+
+// the shared client with the timeout of a specific upstream
+#[derive(Clone)]
+pub struct Upstream {
+	client: reqwest::Client,
+	timeout: Duration,
+}
+
+impl HttpClient {
+	pub fn from_env() -> HttpClient {
+		let user_agent = var("HTTP_USER_AGENT")
+			.unwrap_or_else(|_| format!("TabQ-Website/{} (+https://github.com/CMD-Golem/TabQ-Website)", env!("CARGO_PKG_VERSION")));
+
+		let proxy = match var("HTTP_PROXY_URL") {
+			Ok(proxy_url) if !proxy_url.is_empty() => reqwest::Proxy::all(&proxy_url).inspect_err(|e| eprintln!("[Http] Ignoring invalid HTTP_PROXY_URL {e}")).ok(),
+			_ => None,
+		};
+
+		let builder = || {
+			let builder = reqwest::Client::builder()
+				.user_agent(&user_agent)
+				.connect_timeout(seconds("HTTP_CONNECT_TIMEOUT", 5))
+				.read_timeout(seconds("HTTP_READ_TIMEOUT", 30));
+
+			return match &proxy {
+				Some(proxy) => builder.proxy(proxy.clone()),
+				None => builder,
+			};
+		};
+
+		// per upstream timeouts in the form of name;seconds|name;seconds
+		let mut overrides = HashMap::new();
+
+		for (name, value) in config::parse_map(&var("HTTP_TIMEOUT_OVERRIDES").unwrap_or_default()) {
+			match value.parse() {
+				Ok(secs) => overrides.insert(name, Duration::from_secs(secs)),
+				Err(_) => {
+					eprintln!("[Http] Ignoring invalid timeout for {name}");
+					continue;
+				}
+			};
+		}
+
+		return HttpClient {
+			#[cfg(any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "plugins"))]
+			client: builder().build().expect("[Http] Failed to create HTTP client"),
+			#[cfg(feature = "startpage")]
+			public_client: public_client(builder()).build().expect("[Http] Failed to create HTTP client"),
+			timeout: seconds("HTTP_TIMEOUT", 60),
+			overrides: overrides,
+		};
+	}
+
+	#[cfg(any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "plugins"))]
+	pub fn upstream(&self, name: &str) -> Upstream {
+		return Upstream {
+			client: self.client.clone(),
+			timeout: self.overrides.get(name).copied().unwrap_or(self.timeout),
+		};
+	}
+
+	// same as upstream, but private, loopback and link local addresses can't be reached
+	#[cfg(feature = "startpage")]
+	pub fn public_upstream(&self, name: &str) -> Upstream {
+		return Upstream {
+			client: self.public_client.clone(),
+			timeout: self.overrides.get(name).copied().unwrap_or(self.timeout),
+		};
+	}
+}
+
+// names are checked by the resolver, redirects to literal addresses by the policy
+// a proxy would resolve the names itself, so the proxy of the shared client isn't used
+#[cfg(feature = "startpage")]
+fn public_client(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+	return builder
+		.no_proxy()
+		.dns_resolver(Arc::new(PublicResolver))
+		.redirect(redirect::Policy::custom(|attempt| match attempt.previous().len() {
+			5.. => attempt.error("too many redirects"),
+			_ if !is_public_url(attempt.url()) => attempt.error("redirect to a blocked address"),
+			_ => attempt.follow(),
+		}));
+}
+
+#[cfg(feature = "startpage")]
+struct PublicResolver;
+
+#[cfg(feature = "startpage")]
+impl Resolve for PublicResolver {
+	fn resolve(&self, name: Name) -> Resolving {
+		let host = name.as_str().to_string();
+
+		return Box::pin(async move {
+			let addresses = tokio::task::spawn_blocking(move || (host.as_str(), 0).to_socket_addrs().map(|addresses| (host, addresses.collect::<Vec<_>>()))).await?;
+			let (host, addresses) = addresses?;
+
+			// a name resolving partly to internal addresses is blocked completely
+			if addresses.is_empty() || !addresses.iter().all(|address| is_public_ip(address.ip())) {
+				return Err(format!("{host} resolves to a blocked address").into());
+			}
+
+			return Ok(Box::new(addresses.into_iter()) as Addrs);
+		});
+	}
+}
+
+// http(s) urls whose host is a name or a public address
+#[cfg(feature = "startpage")]
+pub fn is_public_url(url: &Url) -> bool {
+	if !matches!(url.scheme(), "http" | "https") {
+		return false;
+	}
+
+	let Some(host) = url.host_str() else {
+		return false;
+	};
+
+	return match host.trim_start_matches("[").trim_end_matches("]").parse::<IpAddr>() {
+		Ok(ip) => is_public_ip(ip),
+		Err(_) => true,
+	};
+}
+
+#[cfg(feature = "startpage")]
+pub fn is_public_ip(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => return is_public_ipv4(ip),
+		IpAddr::V6(ip) => {
+			if let Some(ip) = ip.to_ipv4_mapped() {
+				return is_public_ipv4(ip);
+			}
+
+			let segments = ip.segments();
+			let embedded = |high: u16, low: u16| Ipv4Addr::from((high as u32) << 16 | low as u32);
+
+			// nat64 addresses embed an ipv4 address
+			if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+				return is_public_ipv4(embedded(segments[6], segments[7]));
+			}
+			// 6to4 relays forward to the ipv4 address after the prefix
+			if segments[0] == 0x2002 {
+				return is_public_ipv4(embedded(segments[1], segments[2]));
+			}
+			// teredo embeds the server and the inverted address of the client
+			if segments[0] == 0x2001 && segments[1] == 0 {
+				return is_public_ipv4(embedded(segments[2], segments[3])) && is_public_ipv4(embedded(!segments[6], !segments[7]));
+			}
+
+			return !(ip.is_unspecified()
+				|| ip.is_loopback()
+				|| ip.is_multicast()
+				|| segments[..6] == [0; 6] // ipv4 compatible
+				|| (segments[0] & 0xfe00) == 0xfc00 // unique local
+				|| (segments[0] & 0xffc0) == 0xfe80 // link local
+				|| (segments[0] & 0xffc0) == 0xfec0 // site local
+				|| (segments[0] == 0x2001 && segments[1] == 0xdb8)); // documentation
+		},
+	}
+}
+
+#[cfg(feature = "startpage")]
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+	let octets = ip.octets();
+
+	return !(ip.is_unspecified()
+		|| ip.is_loopback()
+		|| ip.is_private()
+		|| ip.is_link_local()
+		|| ip.is_broadcast()
+		|| ip.is_documentation()
+		|| ip.is_multicast()
+		|| octets[0] == 0
+		|| octets[0] >= 240 // reserved
+		|| (octets[0] == 100 && (octets[1] & 0xc0) == 64) // carrier grade nat
+		|| (octets[0] == 192 && octets[1] == 0 && octets[2] == 0) // protocol assignments
+		|| (octets[0] == 198 && (octets[1] & 0xfe) == 18)); // benchmarking
+}
+
+impl Upstream {
+	pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+		return self.client.request(method, url).timeout(self.timeout);
+	}
+
+	#[cfg(any(feature = "magazines", feature = "workflow", feature = "startpage", feature = "plugins"))]
+	pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+		return self.request(Method::GET, url);
+	}
+
+	#[cfg(feature = "magazines")]
+	pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+		return self.request(Method::POST, url);
+	}
+}
+
+// without the address checks of public_upstream, for mock servers on loopback addresses in tests
+#[cfg(all(test, feature = "startpage"))]
+impl Upstream {
+	pub fn unrestricted() -> Upstream {
+		return Upstream { client: reqwest::Client::builder().no_proxy().build().unwrap(), timeout: Duration::from_secs(5) };
+	}
+}
+
+fn seconds(name: &str, default: u64) -> Duration {
+	return Duration::from_secs(var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default));
+}
+
+#[cfg(all(test, feature = "startpage"))]
+mod tests {
+	use super::*;
+	use axum::{Router, routing::any};
+
+	#[test]
+	fn blocks_internal_addresses() {
+		for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "64:ff9b::a00:1", "2002:7f00:1::", "2002:a9fe:a9fe::1", "2001:0:4136:e378:8000:63bf:80ff:fffe", "2001:0:7f00:1::1"] {
+			assert!(!is_public_ip(ip.parse::<IpAddr>().unwrap()), "{ip} is internal");
+		}
+
+		for ip in ["1.1.1.1", "2606:4700:4700::1111", "64:ff9b::101:101", "2002:101:101::1", "2001:0:4136:e378:8000:63bf:fefe:fefe"] {
+			assert!(is_public_ip(ip.parse::<IpAddr>().unwrap()), "{ip} is public");
+		}
+
+		assert!(!is_public_url(&Url::parse("http://[::1]:8080/").unwrap()));
+		assert!(!is_public_url(&Url::parse("file:///etc/passwd").unwrap()));
+		assert!(is_public_url(&Url::parse("https://www.srf.ch").unwrap()));
+	}
+
+	#[tokio::test]
+	async fn public_client_bypasses_the_proxy() {
+		// answers every request, like a proxy fetching internal addresses for its clients
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		tokio::spawn(async move { axum::serve(listener, Router::new().fallback(any(|| async { "proxied" }))).await.unwrap() });
+
+		let proxied = || reqwest::Client::builder().proxy(reqwest::Proxy::all(format!("http://127.0.0.1:{port}")).unwrap());
+		let url = format!("http://localhost:{port}/internal");
+
+		let response = proxied().build().unwrap().get(&url).send().await.unwrap();
+		assert_eq!(response.text().await.unwrap(), "proxied");
+
+		assert!(public_client(proxied()).build().unwrap().get(&url).send().await.is_err());
+	}
+}
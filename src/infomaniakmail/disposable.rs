@@ -0,0 +1,292 @@
+use axum::{
+	extract::{Path, State},
+	http::{StatusCode, header::CONTENT_TYPE},
+	response::{IntoResponse, Response}
+};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::{
+	collections::{HashSet, hash_map::RandomState},
+	env::var,
+	hash::{BuildHasher, Hasher},
+	path::PathBuf,
+	time::{Duration, SystemTime, UNIX_EPOCH}
+};
+use tokio::{fs, sync::Mutex};
+
+use crate::{
+	client_ip::ClientIp,
+	error
+};
+use super::{validate_mailbox_name, validate_new_mailbox_name, Infomaniak, NewMailbox};
+
+const DEFAULT_EXPIRY: u64 = 24 * 3600;
+
+// mailboxes and aliases which are deleted again after their expiry, persisted so expiries survive restarts
+pub struct Disposables {
+	pub(super) path: PathBuf,
+	entries: Mutex<Vec<Disposable>>,
+	// names being created upstream, the entries lock isn't held during upstream calls
+	creating: std::sync::Mutex<HashSet<String>>,
+	max_expiry: u64,
+	interval: Duration,
+	// mailbox new aliases are added to when the caller doesn't name one
+	alias_mailbox: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+	Mailbox,
+	Alias,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Disposable {
+	kind: Kind,
+	name: String,
+	// mailbox the alias belongs to, the mailbox itself for mailboxes
+	mailbox: String,
+	created_at: u64,
+	expires_at: u64,
+}
+
+// requests from our callers
+#[derive(Deserialize)]
+struct CreateDisposable {
+	kind: Kind,
+	// generated when missing
+	name: Option<String>,
+	mailbox: Option<String>,
+	// seconds until the mailbox or alias is deleted
+	expires_in: Option<u64>,
+}
+
+// requests to the infomaniak api
+#[derive(Serialize)]
+struct NewAlias<'a> {
+	alias: &'a str,
+}
+
+impl Disposables {
+	pub async fn from_env() -> Disposables {
+		let mut disposables = Disposables::load(PathBuf::from(var("INFOMANIAK_MAIL_STORE").ok().filter(|value| !value.is_empty()).unwrap_or("infomaniakmail.json".to_string()))).await;
+
+		disposables.max_expiry = number("INFOMANIAK_MAIL_MAX_EXPIRY", disposables.max_expiry).max(60);
+		disposables.interval = Duration::from_secs(number("INFOMANIAK_MAIL_EXPIRY_INTERVAL", 60).max(1));
+		disposables.alias_mailbox = var("INFOMANIAK_MAIL_ALIAS_MAILBOX").ok().filter(|value| !value.is_empty());
+
+		return disposables;
+	}
+
+	// restore the entries of an earlier run
+	pub async fn load(path: PathBuf) -> Disposables {
+		let mut entries = vec![];
+
+		match fs::read(&path).await {
+			Ok(content) => match serde_json::from_slice(&content) {
+				Ok(stored) => entries = stored,
+				Err(e) => eprintln!("[Infomaniak Mail] Could not read {}: {e}", path.display()),
+			},
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+			Err(e) => eprintln!("[Infomaniak Mail] Could not read {}: {e}", path.display()),
+		}
+
+		return Disposables {
+			path: path,
+			entries: Mutex::new(entries),
+			creating: std::sync::Mutex::new(HashSet::new()),
+			max_expiry: 30 * 24 * 3600,
+			interval: Duration::from_secs(60),
+			alias_mailbox: None,
+		};
+	}
+
+	// callers hold the entries lock so concurrent changes can't overwrite each other
+	async fn save(&self, entries: &[Disposable]) -> Result<(), Response> {
+		let content = serde_json::to_vec_pretty(entries).map_err(|e| error::map_serde_error(e, "Infomaniak Mail"))?;
+
+		if let Some(parent_folder) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+			fs::create_dir_all(parent_folder).await.unwrap_or_default();
+		}
+
+		let part = self.path.with_extension("part");
+		let write = async {
+			fs::write(&part, content).await?;
+			return fs::rename(&part, &self.path).await;
+		};
+
+		return write.await.map_err(|e| error::generic_internal_error(&format!("[Infomaniak Mail] Could not write {}: {e}", self.path.display())));
+	}
+}
+
+pub async fn create(State(infomaniak): State<Infomaniak>, client: ClientIp, body: String) -> Result<Response, Response> {
+	let request: CreateDisposable = serde_json::from_str(&body).map_err(|e| error::map_invalid_body_error(e, "Infomaniak Mail"))?;
+	let disposables = &infomaniak.disposables;
+
+	let expires_in = request.expires_in.unwrap_or(DEFAULT_EXPIRY);
+	if expires_in == 0 || expires_in > disposables.max_expiry {
+		return Err(error::generic_request_error(&format!("[Infomaniak Mail] expires_in has to be between 1 and {}", disposables.max_expiry)));
+	}
+
+	let name = request.name.unwrap_or_else(random_name);
+	validate_mailbox_name(&name)?;
+
+	let mailbox = match request.kind {
+		Kind::Mailbox => {
+			validate_new_mailbox_name(&name)?;
+			name.clone()
+		},
+		Kind::Alias => match request.mailbox.or_else(|| disposables.alias_mailbox.clone()) {
+			Some(mailbox) => mailbox,
+			None => return Err(error::generic_request_error("[Infomaniak Mail] mailbox is required for aliases")),
+		},
+	};
+	validate_mailbox_name(&mailbox)?;
+
+	let creating = {
+		let entries = disposables.entries.lock().await;
+		let mut creating = disposables.creating.lock().unwrap();
+
+		if entries.iter().any(|entry| entry.name == name) || !creating.insert(name.clone()) {
+			return Err((StatusCode::CONFLICT, format!("{name} already exists")).into_response());
+		}
+
+		Creating { disposables: disposables, name: name.clone() }
+	};
+
+	match request.kind {
+		Kind::Mailbox => {
+			let new_mailbox = NewMailbox { mailbox_name: &name, target: "current_user", link_to_current_user: true };
+			infomaniak.call(Method::POST, "mailboxes", Some(&new_mailbox)).await?;
+		},
+		Kind::Alias => {
+			infomaniak.call(Method::POST, &format!("mailboxes/{mailbox}/aliases"), Some(&NewAlias { alias: &name })).await?;
+		},
+	}
+
+	let created_at = now();
+	let disposable = Disposable { kind: request.kind, name: name, mailbox: mailbox, created_at: created_at, expires_at: created_at + expires_in };
+
+	println!("[Infomaniak Mail] {client} created disposable {} expiring at {}", disposable.name, disposable.expires_at);
+
+	let mut entries = disposables.entries.lock().await;
+	entries.push(disposable.clone());
+	drop(creating);
+	disposables.save(&entries).await?;
+
+	let body = serde_json::to_string(&disposable).map_err(|e| error::map_serde_error(e, "Infomaniak Mail"))?;
+	return Ok((StatusCode::CREATED, [(CONTENT_TYPE, "application/json")], body).into_response());
+}
+
+pub async fn list(State(infomaniak): State<Infomaniak>) -> Result<Response, Response> {
+	let entries = infomaniak.disposables.entries.lock().await.clone();
+	let body = serde_json::to_string(&entries).map_err(|e| error::map_serde_error(e, "Infomaniak Mail"))?;
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, "application/json")], body).into_response());
+}
+
+// delete before the expiry
+pub async fn remove(State(infomaniak): State<Infomaniak>, client: ClientIp, Path(name): Path<String>) -> Result<Response, Response> {
+	let entry = infomaniak.disposables.entries.lock().await.iter().find(|entry| entry.name == name).cloned();
+
+	let Some(entry) = entry else {
+		return Ok((StatusCode::NOT_FOUND, format!("{name} is not a disposable mailbox or alias")).into_response());
+	};
+
+	delete_upstream(&infomaniak, &entry).await?;
+
+	println!("[Infomaniak Mail] {client} removed disposable {name}");
+
+	let mut entries = infomaniak.disposables.entries.lock().await;
+	entries.retain(|entry| entry.name != name);
+	infomaniak.disposables.save(&entries).await?;
+
+	return Ok(StatusCode::NO_CONTENT.into_response());
+}
+
+pub fn spawn(infomaniak: Infomaniak) {
+	tokio::spawn(async move {
+		let mut interval = tokio::time::interval(infomaniak.disposables.interval);
+
+		loop {
+			interval.tick().await;
+			expire(&infomaniak, now()).await;
+		}
+	});
+}
+
+// delete expired mailboxes and aliases, failed deletes are retried on the next run
+pub async fn expire(infomaniak: &Infomaniak, now: u64) {
+	let expired: Vec<Disposable> = infomaniak.disposables.entries.lock().await.iter()
+		.filter(|entry| entry.expires_at <= now)
+		.cloned()
+		.collect();
+
+	let mut deleted = HashSet::new();
+
+	for entry in expired {
+		if delete_upstream(infomaniak, &entry).await.is_ok() {
+			println!("[Infomaniak Mail] Deleted expired {}", entry.name);
+			deleted.insert(entry.name);
+		}
+	}
+
+	if deleted.is_empty() {
+		return;
+	}
+
+	let mut entries = infomaniak.disposables.entries.lock().await;
+	entries.retain(|entry| !deleted.contains(&entry.name));
+	let _ = infomaniak.disposables.save(&entries).await;
+}
+
+// releases the name when the creation failed or the request was cancelled
+struct Creating<'a> {
+	disposables: &'a Disposables,
+	name: String,
+}
+
+impl Drop for Creating<'_> {
+	fn drop(&mut self) {
+		self.disposables.creating.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.name);
+	}
+}
+
+// mailboxes or aliases which were already deleted by hand count as deleted
+async fn delete_upstream(infomaniak: &Infomaniak, entry: &Disposable) -> Result<(), Response> {
+	let path = match entry.kind {
+		Kind::Mailbox => format!("mailboxes/{}", entry.name),
+		Kind::Alias => format!("mailboxes/{}/aliases/{}", entry.mailbox, entry.name),
+	};
+
+	match infomaniak.call::<()>(Method::DELETE, &path, None).await {
+		Ok(_) => return Ok(()),
+		Err(response) if response.status() == StatusCode::NOT_FOUND => return Ok(()),
+		Err(response) => return Err(response),
+	}
+}
+
+// names only need to be hard to guess for outsiders, the hasher is seeded randomly per process
+fn random_name() -> String {
+	let mut hasher = RandomState::new().build_hasher();
+	hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos());
+	let mut value = hasher.finish();
+
+	let mut name = String::from("tmp-");
+	for _ in 0..10 {
+		name.push(char::from_digit((value % 36) as u32, 36).unwrap_or('0'));
+		value /= 36;
+	}
+
+	return name;
+}
+
+fn now() -> u64 {
+	return SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+}
+
+fn number(name: &str, default: u64) -> u64 {
+	return var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default);
+}
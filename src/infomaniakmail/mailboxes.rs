@@ -0,0 +1,115 @@
+use axum::{
+	extract::{Path, State},
+	http::{StatusCode, header::CONTENT_TYPE},
+	response::{IntoResponse, Response}
+};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use crate::{
+	client_ip::ClientIp,
+	error
+};
+use super::{validate_mailbox_name, Infomaniak};
+
+const MAX_TARGETS: usize = 50;
+
+// everything the admin page shows for one mailbox, quota usage is part of the mailbox
+#[derive(Serialize)]
+struct MailboxDetails {
+	mailbox: serde_json::Value,
+	aliases: serde_json::Value,
+	forwarding: serde_json::Value,
+}
+
+// requests from our callers, both replace the current list
+#[derive(Deserialize, Serialize)]
+struct Forwarding {
+	addresses: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Aliases {
+	aliases: Vec<String>,
+}
+
+pub async fn list(State(infomaniak): State<Infomaniak>, client: ClientIp) -> Result<Response, Response> {
+	println!("[Infomaniak Mail] {client} listed mailboxes");
+
+	let data = infomaniak.call::<()>(Method::GET, "mailboxes", None).await?;
+	return Ok(json_response(data.to_string()));
+}
+
+pub async fn details(State(infomaniak): State<Infomaniak>, client: ClientIp, Path(mailbox_name): Path<String>) -> Result<Response, Response> {
+	validate_mailbox_name(&mailbox_name)?;
+
+	println!("[Infomaniak Mail] {client} fetched mailbox {mailbox_name}");
+
+	let mailbox_path = format!("mailboxes/{mailbox_name}");
+	let aliases_path = format!("mailboxes/{mailbox_name}/aliases");
+	let forwarding_path = format!("mailboxes/{mailbox_name}/forwarding");
+
+	let (mailbox, aliases, forwarding) = tokio::try_join!(
+		infomaniak.call::<()>(Method::GET, &mailbox_path, None),
+		infomaniak.call::<()>(Method::GET, &aliases_path, None),
+		infomaniak.call::<()>(Method::GET, &forwarding_path, None)
+	)?;
+
+	let body = serde_json::to_string(&MailboxDetails { mailbox, aliases, forwarding }).map_err(|e| error::map_serde_error(e, "Infomaniak Mail"))?;
+	return Ok(json_response(body));
+}
+
+pub async fn update_forwarding(State(infomaniak): State<Infomaniak>, client: ClientIp, Path(mailbox_name): Path<String>, body: String) -> Result<Response, Response> {
+	validate_mailbox_name(&mailbox_name)?;
+	let request: Forwarding = serde_json::from_str(&body).map_err(|e| error::map_invalid_body_error(e, "Infomaniak Mail"))?;
+
+	if request.addresses.len() > MAX_TARGETS {
+		return Err(error::generic_request_error(&format!("[Infomaniak Mail] At most {MAX_TARGETS} addresses are allowed")));
+	}
+
+	for address in &request.addresses {
+		validate_address(address)?;
+	}
+
+	println!("[Infomaniak Mail] {client} updated forwarding of {mailbox_name}");
+
+	let data = infomaniak.call(Method::PUT, &format!("mailboxes/{mailbox_name}/forwarding"), Some(&request)).await?;
+	return Ok(json_response(data.to_string()));
+}
+
+pub async fn update_aliases(State(infomaniak): State<Infomaniak>, client: ClientIp, Path(mailbox_name): Path<String>, body: String) -> Result<Response, Response> {
+	validate_mailbox_name(&mailbox_name)?;
+	let request: Aliases = serde_json::from_str(&body).map_err(|e| error::map_invalid_body_error(e, "Infomaniak Mail"))?;
+
+	if request.aliases.len() > MAX_TARGETS {
+		return Err(error::generic_request_error(&format!("[Infomaniak Mail] At most {MAX_TARGETS} aliases are allowed")));
+	}
+
+	for alias in &request.aliases {
+		validate_mailbox_name(alias)?;
+	}
+
+	println!("[Infomaniak Mail] {client} updated aliases of {mailbox_name}");
+
+	let data = infomaniak.call(Method::PUT, &format!("mailboxes/{mailbox_name}/aliases"), Some(&request)).await?;
+	return Ok(json_response(data.to_string()));
+}
+
+fn json_response(body: String) -> Response {
+	return (StatusCode::OK, [(CONTENT_TYPE, "application/json")], body).into_response();
+}
+
+// forwarding targets can be on any domain, the rest is checked by infomaniak
+fn validate_address(address: &str) -> Result<(), Response> {
+	let valid = match address.split_once("@") {
+		Some((local, domain)) => !local.is_empty() && domain.contains(".") && !domain.contains("@"),
+		None => false,
+	};
+
+	if !valid || address.len() > 254 || address.chars().any(|c| c.is_whitespace() || c.is_control()) {
+		return Err(error::generic_request_error(&format!("[Infomaniak Mail] Invalid address {address:?}")));
+	}
+
+	return Ok(());
+}
@@ -0,0 +1,391 @@
+use axum::{
+	body::Body,
+	extract::{Path, Request, State},
+	http::{HeaderMap, StatusCode, header::{AUTHORIZATION, CONTENT_TYPE}},
+	middleware::{self, Next},
+	response::{IntoResponse, Response},
+	routing::{delete, get, put},
+	Router
+};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::{
+	env::var,
+	sync::Arc
+};
+use subtle::ConstantTimeEq;
+
+use crate::{
+	client_ip::ClientIp,
+	config,
+	error,
+	http_client::{HttpClient, Upstream}
+};
+
+mod disposable;
+mod mailboxes;
+
+const INFOMANIAK_API: &str = "https://api.infomaniak.com";
+
+#[derive(Clone)]
+struct Infomaniak {
+	upstream: Upstream,
+	api_url: String,
+	api_token: String,
+	// callers of our api have to send this as bearer
+	bearer: String,
+	mail_hosting_id: String,
+	disposables: Arc<disposable::Disposables>,
+}
+
+// requests from our callers
+#[derive(Deserialize)]
+struct CreateMailbox {
+	mailbox_name: String,
+}
+
+// requests to and responses of the infomaniak api
+#[derive(Serialize)]
+struct NewMailbox<'a> {
+	mailbox_name: &'a str,
+	target: &'a str,
+	link_to_current_user: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse {
+	result: String,
+	#[serde(default)]
+	data: serde_json::Value,
+	error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+	#[serde(default)]
+	code: String,
+	#[serde(default)]
+	description: String,
+}
+
+pub async fn router(http_client: &HttpClient) -> Option<Router> {
+	let mut env = config::RequiredEnv::new("Infomaniak Mail");
+
+	let state = Infomaniak {
+		upstream: http_client.upstream("infomaniak"),
+		api_url: var("INFOMANIAK_API_URL").ok().filter(|value| !value.is_empty()).unwrap_or(INFOMANIAK_API.to_string()).trim_end_matches("/").to_string(),
+		api_token: env.get("INFOMANIAK_API_TOKEN"),
+		bearer: env.get("INFOMANIAK_MAIL_BEARER"),
+		mail_hosting_id: env.get("INFOMANIAK_MAIL_HOSTING_ID"),
+		disposables: Arc::new(disposable::Disposables::from_env().await),
+	};
+
+	if !env.is_complete() {
+		return None;
+	}
+
+	disposable::spawn(state.clone());
+
+	return Some(routes(state));
+}
+
+fn routes(state: Infomaniak) -> Router {
+	return Router::new()
+		.route("/", get(mailboxes::list).post(create))
+		.route("/{mailbox_name}", get(mailboxes::details).delete(remove))
+		.route("/{mailbox_name}/forwarding", put(mailboxes::update_forwarding))
+		.route("/{mailbox_name}/aliases", put(mailboxes::update_aliases))
+		.route("/disposable", get(disposable::list).post(disposable::create))
+		.route("/disposable/{name}", delete(disposable::remove))
+		.layer(middleware::from_fn_with_state(state.clone(), authenticate))
+		.with_state(state);
+}
+
+// every route manages our mail hosting, so every caller has to authenticate
+async fn authenticate(State(infomaniak): State<Infomaniak>, client: ClientIp, headers: HeaderMap, req: Request<Body>, next: Next) -> Result<Response, Response> {
+	let bearer = headers
+		.get(AUTHORIZATION)
+		.and_then(|value| value.as_bytes().strip_prefix(b"Bearer "))
+		.ok_or_else(|| error::generic_unauthorized_error(&format!("[Infomaniak Mail] {client} Bearer required")))?;
+
+	if !bool::from(bearer.ct_eq(infomaniak.bearer.as_bytes())) {
+		return Err(error::generic_unauthorized_error(&format!("[Infomaniak Mail] {client} Bearer invalid")));
+	}
+
+	return Ok(next.run(req).await);
+}
+
+async fn create(State(infomaniak): State<Infomaniak>, client: ClientIp, body: String) -> Result<Response, Response> {
+	let request: CreateMailbox = serde_json::from_str(&body).map_err(|e| error::map_invalid_body_error(e, "Infomaniak Mail"))?;
+	validate_new_mailbox_name(&request.mailbox_name)?;
+
+	println!("[Infomaniak Mail] {client} created mailbox {}", request.mailbox_name);
+
+	let mailbox = NewMailbox { mailbox_name: &request.mailbox_name, target: "current_user", link_to_current_user: true };
+	let data = infomaniak.call(Method::POST, "mailboxes", Some(&mailbox)).await?;
+
+	return Ok((StatusCode::CREATED, [(CONTENT_TYPE, "application/json")], data.to_string()).into_response());
+}
+
+async fn remove(State(infomaniak): State<Infomaniak>, client: ClientIp, Path(mailbox_name): Path<String>) -> Result<Response, Response> {
+	validate_mailbox_name(&mailbox_name)?;
+
+	println!("[Infomaniak Mail] {client} removed mailbox {mailbox_name}");
+
+	infomaniak.call::<()>(Method::DELETE, &format!("mailboxes/{mailbox_name}"), None).await?;
+
+	return Ok(StatusCode::NO_CONTENT.into_response());
+}
+
+impl Infomaniak {
+	// call an endpoint of our mail hosting and unwrap the data of the response
+	async fn call<T: Serialize>(&self, method: Method, path: &str, body: Option<&T>) -> Result<serde_json::Value, Response> {
+		let mut request = self.upstream.request(method, format!("{}/1/mail_hostings/{}/{path}", self.api_url, self.mail_hosting_id))
+			.bearer_auth(&self.api_token);
+
+		if let Some(body) = body {
+			request = request.json(body);
+		}
+
+		let response = request.send().await.map_err(|e| error::map_reqwest_error(e, "Infomaniak Mail"))?;
+		let status = response.status();
+		let content = response.text().await.map_err(|e| error::map_reqwest_error(e, "Infomaniak Mail"))?;
+
+		let response: ApiResponse = serde_json::from_str(&content).map_err(|e| error::map_serde_error(e, "Infomaniak Mail"))?;
+
+		if status.is_success() && response.result == "success" {
+			return Ok(response.data);
+		}
+
+		// the token is ours, so auth errors of infomaniak are a server problem and not the callers
+		let status = match status {
+			StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => StatusCode::BAD_GATEWAY,
+			status if status.is_success() => StatusCode::BAD_GATEWAY,
+			status => status,
+		};
+
+		let (code, description) = response.error.map(|error| (error.code, error.description)).unwrap_or_default();
+		eprintln!("[Infomaniak Mail] {path} failed with {status}: {code} {description}");
+
+		return Err((status, description).into_response());
+	}
+}
+
+// local part of the address, infomaniak allows letters, digits, dots, dashes and underscores
+fn validate_mailbox_name(mailbox_name: &str) -> Result<(), Response> {
+	let valid_chars = mailbox_name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || ['.', '-', '_'].contains(&c));
+
+	if mailbox_name.is_empty() || mailbox_name.len() > 64 || !valid_chars || mailbox_name.starts_with(".") || mailbox_name.ends_with(".") {
+		return Err(error::generic_request_error(&format!("[Infomaniak Mail] Invalid mailbox_name {mailbox_name:?}")));
+	}
+
+	return Ok(());
+}
+
+// static routes next to /{mailbox_name}, mailboxes named like them couldn't be managed through this api
+const RESERVED_MAILBOX_NAMES: [&str; 1] = ["disposable"];
+
+fn validate_new_mailbox_name(mailbox_name: &str) -> Result<(), Response> {
+	validate_mailbox_name(mailbox_name)?;
+
+	if RESERVED_MAILBOX_NAMES.contains(&mailbox_name) {
+		return Err(error::generic_request_error(&format!("[Infomaniak Mail] mailbox_name {mailbox_name:?} is reserved")));
+	}
+
+	return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{body, http::Request, routing::any};
+	use tower::ServiceExt;
+
+	const TOKEN: &str = "api-token";
+	const BEARER: &str = "caller-bearer";
+
+	// minimal stand-in for the mailbox endpoints of the infomaniak api
+	async fn mock(Path(params): Path<Vec<(String, String)>>, method: Method, uri: axum::http::Uri, headers: HeaderMap, body: String) -> Response {
+		if headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()) != Some(&format!("Bearer {TOKEN}")) {
+			return (StatusCode::UNAUTHORIZED, r#"{"result":"error","error":{"code":"not_authorized","description":"Token invalid"}}"#).into_response();
+		}
+
+		let hosting = &params[0].1;
+		// mailbox or alias the request is about
+		let target = params.get(1).and(params.last()).map(|(_, value)| value.as_str());
+
+		if method == Method::GET || method == Method::PUT {
+			let data = match uri.path().rsplit("/").next() {
+				Some("mailboxes") => r#"[{"mailbox_name":"inbox"}]"#.to_string(),
+				Some("aliases") | Some("forwarding") if method == Method::PUT => body,
+				Some("aliases") => r#"{"aliases":["shop"]}"#.to_string(),
+				Some("forwarding") => r#"{"addresses":[]}"#.to_string(),
+				_ => r#"{"mailbox_name":"inbox","quota":{"used":1024,"total":20480}}"#.to_string(),
+			};
+			return (StatusCode::OK, format!(r#"{{"result":"success","data":{data}}}"#)).into_response();
+		}
+
+		return match (method, target) {
+			(Method::POST, None) if body.contains(r#""mailbox_name":"taken""#) =>
+				(StatusCode::UNPROCESSABLE_ENTITY, r#"{"result":"error","error":{"code":"mailbox_already_exists","description":"Mailbox already exists"}}"#).into_response(),
+			(Method::POST, None) => (StatusCode::OK, format!(r#"{{"result":"success","data":{{"hosting":{hosting},"request":{body}}}}}"#)).into_response(),
+			(Method::POST, Some(_)) => (StatusCode::OK, r#"{"result":"success","data":true}"#).into_response(),
+			(Method::DELETE, Some("gone")) => (StatusCode::NOT_FOUND, r#"{"result":"error","error":{"code":"not_found","description":"Not found"}}"#).into_response(),
+			(Method::DELETE, Some(_)) => (StatusCode::OK, r#"{"result":"success","data":true}"#).into_response(),
+			_ => (StatusCode::METHOD_NOT_ALLOWED, r#"{"result":"error"}"#).into_response(),
+		};
+	}
+
+	async fn state(api_token: &str) -> Infomaniak {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+
+		let api = Router::new()
+			.route("/1/mail_hostings/{hosting}/mailboxes", any(mock))
+			.route("/1/mail_hostings/{hosting}/mailboxes/{mailbox}", any(mock))
+			.route("/1/mail_hostings/{hosting}/mailboxes/{mailbox}/aliases", any(mock))
+			.route("/1/mail_hostings/{hosting}/mailboxes/{mailbox}/aliases/{alias}", any(mock))
+			.route("/1/mail_hostings/{hosting}/mailboxes/{mailbox}/forwarding", any(mock));
+		// the store is removed once the runtime of the test drops the mock api
+		let dir = crate::temp_dir::TempDir::new("infomaniakmail");
+		let store = dir.path().join("infomaniakmail.json");
+
+		tokio::spawn(async move {
+			let _dir = dir;
+			axum::serve(listener, api).await.unwrap();
+		});
+
+		return Infomaniak {
+			upstream: HttpClient::from_env().upstream("infomaniak"),
+			api_url: format!("http://{address}"),
+			api_token: api_token.to_string(),
+			bearer: BEARER.to_string(),
+			mail_hosting_id: "42".to_string(),
+			disposables: Arc::new(disposable::Disposables::load(store).await),
+		};
+	}
+
+	async fn app(api_token: &str) -> Router {
+		return routes(state(api_token).await);
+	}
+
+	async fn send(app: Router, method: &str, uri: &str, bearer: Option<&str>, body: &str) -> (StatusCode, String) {
+		let mut request = Request::builder().method(method).uri(uri);
+		if let Some(bearer) = bearer {
+			request = request.header(AUTHORIZATION, format!("Bearer {bearer}"));
+		}
+
+		let response = app.oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+		let status = response.status();
+		let content = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+		return (status, String::from_utf8_lossy(&content).to_string());
+	}
+
+	#[tokio::test]
+	async fn rejects_callers_without_bearer() {
+		assert_eq!(send(app(TOKEN).await, "POST", "/", None, r#"{"mailbox_name":"test"}"#).await.0, StatusCode::UNAUTHORIZED);
+		assert_eq!(send(app(TOKEN).await, "DELETE", "/test", Some("wrong"), "").await.0, StatusCode::UNAUTHORIZED);
+	}
+
+	#[tokio::test]
+	async fn creates_mailbox_with_token() {
+		let (status, body) = send(app(TOKEN).await, "POST", "/", Some(BEARER), r#"{"mailbox_name":"shop.2024"}"#).await;
+
+		assert_eq!(status, StatusCode::CREATED);
+		let data: serde_json::Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(data["hosting"], 42);
+		assert_eq!(data["request"]["mailbox_name"], "shop.2024");
+		assert_eq!(data["request"]["link_to_current_user"], true);
+	}
+
+	#[tokio::test]
+	async fn deletes_mailbox() {
+		assert_eq!(send(app(TOKEN).await, "DELETE", "/shop.2024", Some(BEARER), "").await.0, StatusCode::NO_CONTENT);
+	}
+
+	#[tokio::test]
+	async fn validates_requests() {
+		assert_eq!(send(app(TOKEN).await, "POST", "/", Some(BEARER), r#"{"mailbox_name":"Not Valid"}"#).await.0, StatusCode::BAD_REQUEST);
+		assert_eq!(send(app(TOKEN).await, "POST", "/", Some(BEARER), r#"{"name":"test"}"#).await.0, StatusCode::BAD_REQUEST);
+		assert_eq!(send(app(TOKEN).await, "POST", "/", Some(BEARER), r#"{"mailbox_name":"disposable"}"#).await.0, StatusCode::BAD_REQUEST);
+		assert_eq!(send(app(TOKEN).await, "POST", "/disposable", Some(BEARER), r#"{"kind":"mailbox","name":"disposable"}"#).await.0, StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn forwards_api_errors() {
+		let (status, body) = send(app(TOKEN).await, "POST", "/", Some(BEARER), r#"{"mailbox_name":"taken"}"#).await;
+		assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+		assert_eq!(body, "Mailbox already exists");
+
+		// a rejected api token is not the fault of the caller
+		assert_eq!(send(app("expired").await, "DELETE", "/test", Some(BEARER), "").await.0, StatusCode::BAD_GATEWAY);
+	}
+
+	#[tokio::test]
+	async fn expires_disposable_aliases() {
+		let state = state(TOKEN).await;
+		let app = routes(state.clone());
+
+		let (status, body) = send(app.clone(), "POST", "/disposable", Some(BEARER), r#"{"kind":"alias","name":"shop","mailbox":"inbox","expires_in":60}"#).await;
+		assert_eq!(status, StatusCode::CREATED);
+		assert!(body.contains(r#""mailbox":"inbox""#));
+
+		assert_eq!(send(app.clone(), "POST", "/disposable", Some(BEARER), r#"{"kind":"alias","name":"shop","mailbox":"inbox"}"#).await.0, StatusCode::CONFLICT);
+		assert_eq!(send(app.clone(), "POST", "/disposable", Some(BEARER), r#"{"kind":"alias","name":"other"}"#).await.0, StatusCode::BAD_REQUEST);
+
+		// a failed creation releases the name again
+		for _ in 0..2 {
+			assert_eq!(send(app.clone(), "POST", "/disposable", Some(BEARER), r#"{"kind":"mailbox","name":"taken"}"#).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+		}
+
+		let (_, body) = send(app.clone(), "POST", "/disposable", Some(BEARER), r#"{"kind":"mailbox"}"#).await;
+		assert!(body.contains(r#""name":"tmp-"#));
+
+		// not expired yet
+		disposable::expire(&state, 0).await;
+		let (_, body) = send(app.clone(), "GET", "/disposable", Some(BEARER), "").await;
+		assert_eq!(serde_json::from_str::<Vec<serde_json::Value>>(&body).unwrap().len(), 2);
+
+		disposable::expire(&state, u64::MAX).await;
+		let (_, body) = send(app.clone(), "GET", "/disposable", Some(BEARER), "").await;
+		assert_eq!(body, "[]");
+
+		// the store was written and survives a restart
+		let stored: Vec<serde_json::Value> = serde_json::from_str(&std::fs::read_to_string(&state.disposables.path).unwrap()).unwrap();
+		assert!(stored.is_empty());
+	}
+
+	#[tokio::test]
+	async fn removes_disposables_deleted_by_hand() {
+		let app = app(TOKEN).await;
+
+		assert_eq!(send(app.clone(), "POST", "/disposable", Some(BEARER), r#"{"kind":"mailbox","name":"gone"}"#).await.0, StatusCode::CREATED);
+		assert_eq!(send(app.clone(), "DELETE", "/disposable/gone", Some(BEARER), "").await.0, StatusCode::NO_CONTENT);
+		assert_eq!(send(app.clone(), "DELETE", "/disposable/gone", Some(BEARER), "").await.0, StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn manages_mailboxes() {
+		let app = app(TOKEN).await;
+
+		let (status, body) = send(app.clone(), "GET", "/", Some(BEARER), "").await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body, r#"[{"mailbox_name":"inbox"}]"#);
+
+		let (_, body) = send(app.clone(), "GET", "/inbox", Some(BEARER), "").await;
+		let details: serde_json::Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(details["mailbox"]["quota"]["used"], 1024);
+		assert_eq!(details["aliases"]["aliases"][0], "shop");
+		assert!(details["forwarding"]["addresses"].as_array().unwrap().is_empty());
+
+		let (status, body) = send(app.clone(), "PUT", "/inbox/forwarding", Some(BEARER), r#"{"addresses":["me@example.org"]}"#).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(body, r#"{"addresses":["me@example.org"]}"#);
+
+		assert_eq!(send(app.clone(), "PUT", "/inbox/forwarding", Some(BEARER), r#"{"addresses":["not an address"]}"#).await.0, StatusCode::BAD_REQUEST);
+		assert_eq!(send(app.clone(), "PUT", "/inbox/aliases", Some(BEARER), r#"{"aliases":["shop","news"]}"#).await.0, StatusCode::OK);
+		assert_eq!(send(app.clone(), "PUT", "/inbox/aliases", Some(BEARER), r#"{"aliases":["Bad Alias"]}"#).await.0, StatusCode::BAD_REQUEST);
+	}
+}
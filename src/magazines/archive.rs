@@ -0,0 +1,377 @@
+use axum::{
+	extract::State,
+	response::Response
+};
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::{
+	env::var,
+	path::PathBuf,
+	time::Duration
+};
+use tokio::fs;
+
+use crate::error;
+use super::{date, default_edition, json_response, Magazines, Page, Publication};
+
+const EDITION_FILE: &str = "edition.json";
+
+// local copy of past editions which is used when upstream doesn't have them anymore
+pub struct Archive {
+	dir: PathBuf,
+	interval: Duration,
+	lookback: u64,
+	retention: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ArchivedEdition {
+	pub provider: String,
+	#[serde(flatten)]
+	pub publication: Publication,
+	pub pages: Vec<Page>,
+}
+
+impl Archive {
+	pub fn from_env() -> Option<Archive> {
+		let dir = var("MAGAZINES_ARCHIVE_DIR").ok().filter(|value| !value.is_empty())?;
+		let number = |name: &str, default: u64| var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default);
+
+		return Some(Archive {
+			dir: PathBuf::from(dir),
+			interval: Duration::from_secs(number("MAGAZINES_ARCHIVE_INTERVAL", 6 * 3600).max(60)),
+			lookback: number("MAGAZINES_ARCHIVE_LOOKBACK", 2).max(1),
+			retention: number("MAGAZINES_ARCHIVE_RETENTION", 104) as usize,
+		});
+	}
+
+	// dates come from requests and upstream, anything but YYYY-MM-DD could leave the archive dir
+	fn edition_dir(&self, provider: &str, publication_date: &str) -> Option<PathBuf> {
+		date::parse_date(publication_date)?;
+		return Some(self.dir.join(provider).join(publication_date));
+	}
+
+	pub fn image_path(&self, provider: &str, publication_date: &str, page: usize, resolution: &str) -> Option<PathBuf> {
+		return Some(self.edition_dir(provider, publication_date)?.join(format!("{page}-{resolution}")));
+	}
+
+	pub async fn edition(&self, provider: &str, publication_date: &str) -> Option<ArchivedEdition> {
+		let content = fs::read(self.edition_dir(provider, publication_date)?.join(EDITION_FILE)).await.ok()?;
+		return serde_json::from_slice(&content).ok();
+	}
+
+	// archived publication dates of a provider, newest first
+	async fn dates(&self, provider: &str) -> Vec<String> {
+		let mut dates = vec![];
+		let Ok(mut entries) = fs::read_dir(self.dir.join(provider)).await else {
+			return dates;
+		};
+
+		while let Ok(Some(entry)) = entries.next_entry().await {
+			let name = entry.file_name().to_string_lossy().to_string();
+
+			if date::parse_date(&name).is_some() && fs::try_exists(entry.path().join(EDITION_FILE)).await.unwrap_or(false) {
+				dates.push(name);
+			}
+		}
+
+		dates.sort_unstable_by(|a, b| b.cmp(a));
+		return dates;
+	}
+
+	// editions which were started but never completed, e.g. by a crash during a download
+	async fn incomplete_dirs(&self, provider: &str) -> Vec<PathBuf> {
+		let mut dirs = vec![];
+		let Ok(mut entries) = fs::read_dir(self.dir.join(provider)).await else {
+			return dirs;
+		};
+
+		while let Ok(Some(entry)) = entries.next_entry().await {
+			if entry.file_type().await.is_ok_and(|file_type| file_type.is_dir()) && !fs::try_exists(entry.path().join(EDITION_FILE)).await.unwrap_or(true) {
+				dirs.push(entry.path());
+			}
+		}
+
+		return dirs;
+	}
+
+	pub async fn week(&self, provider: &str, year: i32, week: u32) -> Option<Publication> {
+		let monday = date::iso_week_monday(year, week);
+		let first_day = date::format_date(monday);
+		let last_day = date::format_date(monday + 6);
+
+		let publication_date = self.dates(provider).await.into_iter()
+			.find(|publication_date| *publication_date >= first_day && *publication_date <= last_day)?;
+
+		return self.edition(provider, &publication_date).await.map(|edition| edition.publication);
+	}
+
+	pub async fn publications(&self, provider: &str, publication_date: &str, amount: u64) -> Vec<Publication> {
+		let mut publications = vec![];
+
+		for archived_date in self.dates(provider).await {
+			if publications.len() as u64 >= amount {
+				break;
+			}
+
+			if archived_date.as_str() <= publication_date && let Some(edition) = self.edition(provider, &archived_date).await {
+				publications.push(edition.publication);
+			}
+		}
+
+		return publications;
+	}
+
+	async fn list(&self, providers: &[&str]) -> Vec<ArchivedEdition> {
+		let mut editions = vec![];
+
+		for provider in providers {
+			for publication_date in self.dates(provider).await {
+				if let Some(edition) = self.edition(provider, &publication_date).await {
+					editions.push(edition);
+				}
+			}
+		}
+
+		return editions;
+	}
+}
+
+// upstream and archived publications newest first, upstream wins for dates both have
+pub fn merge(mut publications: Vec<Publication>, archived: Vec<Publication>, amount: u64) -> Vec<Publication> {
+	for publication in archived {
+		if !publications.iter().any(|known| known.publication_date == publication.publication_date) {
+			publications.push(publication);
+		}
+	}
+
+	publications.sort_by(|a, b| b.publication_date.cmp(&a.publication_date));
+	publications.truncate(amount as usize);
+	return publications;
+}
+
+#[derive(Serialize)]
+struct ArchiveEntry {
+	provider: String,
+	#[serde(flatten)]
+	publication: Publication,
+	pages: usize,
+}
+
+pub async fn list(State(magazines): State<Magazines>) -> Result<Response, Response> {
+	let Some(archive) = &magazines.archive else {
+		return Err(error::generic_unavailable_error("[Magazines-Archive] Archive is not enabled"));
+	};
+
+	let mut providers: Vec<&str> = magazines.providers.keys().copied().collect();
+	providers.sort_unstable();
+
+	let entries: Vec<ArchiveEntry> = archive.list(&providers).await.into_iter()
+		.map(|edition| ArchiveEntry { provider: edition.provider, publication: edition.publication, pages: edition.pages.len() })
+		.collect();
+
+	return json_response(&entries);
+}
+
+// archive new editions on a schedule
+pub fn spawn(magazines: Magazines) {
+	tokio::spawn(async move {
+		let Some(archive) = magazines.archive.clone() else {
+			return;
+		};
+
+		println!("[Magazines-Archive] Archiving to {} every {}s", archive.dir.display(), archive.interval.as_secs());
+
+		// archived editions stay searchable even without a persisted index
+		let mut providers: Vec<&str> = magazines.providers.keys().copied().collect();
+		providers.sort_unstable();
+
+		for edition in archive.list(&providers).await {
+			magazines.search.add(&edition.provider, &edition.publication.publication_date, &edition.pages).await;
+		}
+
+		let mut interval = tokio::time::interval(archive.interval);
+
+		loop {
+			interval.tick().await;
+			run(&magazines, &archive).await;
+		}
+	});
+}
+
+async fn run(magazines: &Magazines, archive: &Archive) {
+	let today = date::format_date(date::today());
+
+	let mut providers: Vec<&str> = magazines.providers.keys().copied().collect();
+	providers.sort_unstable();
+
+	for provider in providers {
+		let Ok(source) = magazines.provider(provider) else {
+			continue;
+		};
+
+		let publications = match source.publications(&today, archive.lookback, default_edition(&source)).await {
+			Ok(publications) => publications,
+			Err(_) => {
+				eprintln!("[Magazines-Archive] Could not load {provider} publications");
+				continue;
+			}
+		};
+
+		for publication in publications {
+			if archive.edition(provider, &publication.publication_date).await.is_some() {
+				continue;
+			}
+
+			match archive_edition(magazines, archive, provider, publication).await {
+				Ok(count) => println!("[Magazines-Archive] Archived {provider} edition with {count} pages"),
+				Err(e) => eprintln!("[Magazines-Archive] {provider} {e}"),
+			}
+		}
+
+		enforce_retention(archive, provider).await;
+	}
+}
+
+async fn archive_edition(magazines: &Magazines, archive: &Archive, provider: &str, publication: Publication) -> Result<usize, String> {
+	let source = magazines.provider(provider).map_err(|_| "Unknown provider".to_string())?;
+	let publication_date = publication.publication_date.clone();
+	let resolution = source.default_resolution();
+
+	let Some(edition_dir) = archive.edition_dir(provider, &publication_date) else {
+		return Err(format!("Invalid publication date {publication_date:?}"));
+	};
+
+	let pages = source.pages(&publication_date, default_edition(&source)).await.map_err(|_| format!("Could not load pages of {publication_date}"))?;
+
+	if pages.is_empty() {
+		return Err(format!("{publication_date} has no pages yet"));
+	}
+
+	fs::create_dir_all(&edition_dir).await.map_err(|e| e.to_string())?;
+
+	// a partial download would never be retried nor removed by the retention
+	if let Err(e) = download_edition(magazines, archive, provider, &publication, &pages, resolution).await {
+		fs::remove_dir_all(&edition_dir).await.unwrap_or_default();
+		return Err(e);
+	}
+
+	let edition = ArchivedEdition { provider: provider.to_string(), publication: publication, pages: pages };
+	magazines.search.add(provider, &publication_date, &edition.pages).await;
+
+	return Ok(edition.pages.len());
+}
+
+async fn download_edition(magazines: &Magazines, archive: &Archive, provider: &str, publication: &Publication, pages: &[Page], resolution: &str) -> Result<(), String> {
+	let publication_date = &publication.publication_date;
+	let edition_dir = archive.edition_dir(provider, publication_date).ok_or("Invalid publication date")?;
+
+	for (index, page) in pages.iter().enumerate() {
+		let Some(url) = page.resolutions.get(resolution) else {
+			continue;
+		};
+
+		let url = magazines.allowed_image_url(url).map_err(|_| format!("{url} is not allowed"))?;
+
+		let content = magazines.images.get(url)
+			.send().await.map_err(|e| e.to_string())?
+			.error_for_status().map_err(|e| e.to_string())?
+			.bytes().await.map_err(|e| e.to_string())?;
+
+		fs::write(edition_dir.join(format!("{}-{resolution}", index + 1)), content).await.map_err(|e| e.to_string())?;
+	}
+
+	// the edition file is written last and marks the edition as complete
+	let edition = ArchivedEdition { provider: provider.to_string(), publication: publication.clone(), pages: pages.to_vec() };
+	let content = serde_json::to_vec(&edition).map_err(|e| e.to_string())?;
+
+	fs::write(edition_dir.join("edition.json.part"), content).await.map_err(|e| e.to_string())?;
+	return fs::rename(edition_dir.join("edition.json.part"), edition_dir.join(EDITION_FILE)).await.map_err(|e| e.to_string());
+}
+
+// only keep the newest editions of each provider
+async fn enforce_retention(archive: &Archive, provider: &str) {
+	for dir in archive.incomplete_dirs(provider).await {
+		match fs::remove_dir_all(&dir).await {
+			Ok(_) => println!("[Magazines-Archive] Removed incomplete {}", dir.display()),
+			Err(e) => eprintln!("[Magazines-Archive] {} {e}", dir.display()),
+		}
+	}
+
+	if archive.retention == 0 {
+		return;
+	}
+
+	for publication_date in archive.dates(provider).await.into_iter().skip(archive.retention) {
+		let Some(edition_dir) = archive.edition_dir(provider, &publication_date) else {
+			continue;
+		};
+
+		match fs::remove_dir_all(edition_dir).await {
+			Ok(_) => println!("[Magazines-Archive] Removed {provider} {publication_date}"),
+			Err(e) => eprintln!("[Magazines-Archive] {provider} {publication_date} {e}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::temp_dir::TempDir;
+
+	fn publication(publication_date: &str, edition_number: u64) -> Publication {
+		return Publication { edition_number, edition_volume: 2024, publication_date: publication_date.to_string(), inlays: vec![] };
+	}
+
+	fn archive(dir: &TempDir) -> Archive {
+		return Archive { dir: dir.path().to_path_buf(), interval: Duration::from_secs(60), lookback: 1, retention: 1 };
+	}
+
+	#[test]
+	fn merges_archived_publications() {
+		let upstream = vec![publication("2024-01-30", 5), publication("2024-01-16", 3)];
+		let archived = vec![publication("2024-01-23", 4), publication("2024-01-16", 0), publication("2024-01-09", 2)];
+
+		let merged = merge(upstream, archived, 3);
+		let editions: Vec<u64> = merged.iter().map(|publication| publication.edition_number).collect();
+
+		assert_eq!(editions, vec![5, 4, 3]);
+	}
+
+	#[test]
+	fn rejects_invalid_dates() {
+		let dir = TempDir::new("magazines-archive");
+		let archive = archive(&dir);
+
+		assert!(archive.edition_dir("coop", "2024-01-30").is_some());
+		for invalid in ["../../etc", "2024-01-30/..", "", "2024-02-30"] {
+			assert!(archive.edition_dir("coop", invalid).is_none(), "{invalid}");
+			assert!(archive.image_path("coop", invalid, 1, "preview").is_none(), "{invalid}");
+		}
+	}
+
+	#[tokio::test]
+	async fn removes_incomplete_and_old_editions() {
+		let dir = TempDir::new("magazines-archive");
+		let archive = archive(&dir);
+
+		for publication_date in ["2024-01-23", "2024-01-30"] {
+			let edition = ArchivedEdition { provider: "coop".to_string(), publication: publication(publication_date, 1), pages: vec![] };
+			let edition_dir = archive.edition_dir("coop", publication_date).unwrap();
+			fs::create_dir_all(&edition_dir).await.unwrap();
+			fs::write(edition_dir.join(EDITION_FILE), serde_json::to_vec(&edition).unwrap()).await.unwrap();
+		}
+
+		let partial = archive.edition_dir("coop", "2024-02-06").unwrap();
+		fs::create_dir_all(&partial).await.unwrap();
+		fs::write(partial.join("1-preview"), b"jpeg").await.unwrap();
+
+		assert_eq!(archive.incomplete_dirs("coop").await, vec![partial.clone()]);
+
+		enforce_retention(&archive, "coop").await;
+
+		assert!(!fs::try_exists(&partial).await.unwrap());
+		assert_eq!(archive.dates("coop").await, vec!["2024-01-30".to_string()]);
+		assert_eq!(archive.publications("coop", "2024-12-31", 5).await.len(), 1);
+	}
+}
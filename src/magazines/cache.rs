@@ -0,0 +1,289 @@
+use axum::response::Response;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json;
+use std::{
+	collections::HashMap,
+	future::Future,
+	path::{Path, PathBuf},
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicBool, Ordering}
+	},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+use tokio::{fs, sync::OnceCell};
+
+// keys are partly chosen by clients, so both copies are bounded
+const MAX_ENTRIES: usize = 1000;
+const MAX_DISK_ENTRIES: usize = 10_000;
+// empty results are mostly editions which aren't published yet, they are retried soon and not stored on disk
+const EMPTY_TTL: Duration = Duration::from_secs(60);
+
+// in memory cache with optional copy on disk, concurrent lookups of the same key share one fetch
+pub struct Cache<V> {
+	name: &'static str,
+	entries: Mutex<HashMap<String, Slot<V>>>,
+	disk_dir: Option<PathBuf>,
+	max_entries: usize,
+	max_disk_entries: usize,
+}
+
+struct Slot<V> {
+	cell: Arc<OnceCell<Entry<V>>>,
+	used: Instant,
+}
+
+struct Entry<V> {
+	value: V,
+	expires: Instant,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DiskEntry<V> {
+	expires: u64,
+	value: V,
+}
+
+pub trait CacheValue {
+	fn is_empty(&self) -> bool;
+}
+
+impl<T> CacheValue for Vec<T> {
+	fn is_empty(&self) -> bool {
+		return Vec::is_empty(self);
+	}
+}
+
+impl<T> CacheValue for Option<T> {
+	fn is_empty(&self) -> bool {
+		return self.is_none();
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CacheStatus {
+	Hit,
+	Miss,
+}
+
+impl CacheStatus {
+	pub fn as_str(&self) -> &'static str {
+		return match self {
+			CacheStatus::Hit => "HIT",
+			CacheStatus::Miss => "MISS",
+		};
+	}
+
+	// a response built from several lookups is only a hit when all of them were
+	pub fn and(self, other: CacheStatus) -> CacheStatus {
+		return if self == CacheStatus::Hit && other == CacheStatus::Hit { CacheStatus::Hit } else { CacheStatus::Miss };
+	}
+}
+
+impl<V: Clone + Serialize + DeserializeOwned + CacheValue> Cache<V> {
+	pub fn new(name: &'static str, disk_dir: Option<PathBuf>) -> Cache<V> {
+		return Cache { name, entries: Mutex::new(HashMap::new()), disk_dir, max_entries: MAX_ENTRIES, max_disk_entries: MAX_DISK_ENTRIES };
+	}
+
+	pub async fn get_or_fetch<F>(&self, key: &str, ttl: Duration, fetch: F) -> Result<(V, CacheStatus), Response>
+	where
+		F: Future<Output = Result<V, Response>>
+	{
+		let cell = {
+			let now = Instant::now();
+			let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+			if entries.len() >= self.max_entries && !entries.contains_key(key) {
+				entries.retain(|_, slot| slot.cell.get().is_none_or(|entry| entry.expires > now));
+
+				// least recently used entries make room, a running fetch keeps its cell for those waiting on it
+				while entries.len() >= self.max_entries {
+					let Some(oldest) = entries.iter().min_by_key(|(_, slot)| slot.used).map(|(key, _)| key.clone()) else {
+						break;
+					};
+					entries.remove(&oldest);
+				}
+			}
+
+			let slot = entries.entry(key.to_string()).or_insert_with(|| Slot { cell: Arc::default(), used: now });
+			slot.used = now;
+
+			// expired entries get a fresh cell so the next lookup fetches again
+			if slot.cell.get().is_some_and(|entry| entry.expires <= now) {
+				slot.cell = Arc::new(OnceCell::new());
+			}
+
+			slot.cell.clone()
+		};
+
+		let fetched = AtomicBool::new(false);
+
+		let entry = cell.get_or_try_init(|| async {
+			if let Some(entry) = self.read_disk(key).await {
+				return Ok(entry);
+			}
+
+			fetched.store(true, Ordering::Relaxed);
+			let value = fetch.await?;
+
+			if value.is_empty() {
+				return Ok(Entry { value, expires: Instant::now() + ttl.min(EMPTY_TTL) });
+			}
+
+			self.write_disk(key, &value, ttl).await;
+			return Ok(Entry { value, expires: Instant::now() + ttl });
+		}).await?;
+
+		let status = if fetched.load(Ordering::Relaxed) { CacheStatus::Miss } else { CacheStatus::Hit };
+		return Ok((entry.value.clone(), status));
+	}
+
+	fn disk_path(&self, key: &str) -> Option<PathBuf> {
+		let file_name: String = key.chars()
+			.map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+			.collect();
+
+		return self.disk_dir.as_ref().map(|dir| dir.join(self.name).join(format!("{file_name}.json")));
+	}
+
+	async fn read_disk(&self, key: &str) -> Option<Entry<V>> {
+		let path = self.disk_path(key)?;
+		let content = fs::read(&path).await.ok()?;
+		let entry: DiskEntry<V> = serde_json::from_slice(&content).ok()?;
+
+		let Some(remaining) = entry.expires.checked_sub(unix_now()).filter(|remaining| *remaining > 0) else {
+			fs::remove_file(&path).await.unwrap_or_default();
+			return None;
+		};
+
+		return Some(Entry { value: entry.value, expires: Instant::now() + Duration::from_secs(remaining) });
+	}
+
+	async fn write_disk(&self, key: &str, value: &V, ttl: Duration) {
+		let Some(path) = self.disk_path(key) else {
+			return;
+		};
+
+		let entry = DiskEntry { expires: unix_now() + ttl.as_secs(), value: value };
+		let content = match serde_json::to_vec(&entry) {
+			Ok(content) => content,
+			Err(e) => {
+				eprintln!("[Magazines-Cache] {key} {e}");
+				return;
+			}
+		};
+
+		if let Some(parent_folder) = path.parent() {
+			fs::create_dir_all(parent_folder).await.unwrap_or_default();
+		}
+
+		if let Err(e) = fs::write(&path, content).await {
+			eprintln!("[Magazines-Cache] {key} {e}");
+		}
+
+		if let Some(parent_folder) = path.parent() {
+			self.prune_disk(parent_folder).await;
+		}
+	}
+
+	// drops the oldest files beyond max_disk_entries
+	async fn prune_disk(&self, dir: &Path) {
+		let Ok(mut read_dir) = fs::read_dir(dir).await else {
+			return;
+		};
+
+		let mut files = vec![];
+		while let Ok(Some(file)) = read_dir.next_entry().await {
+			let modified = file.metadata().await.and_then(|metadata| metadata.modified()).unwrap_or(UNIX_EPOCH);
+			files.push((modified, file.path()));
+		}
+
+		if files.len() <= self.max_disk_entries {
+			return;
+		}
+
+		files.sort();
+		let count_removed = files.len() - self.max_disk_entries;
+
+		for (_, path) in files.into_iter().take(count_removed) {
+			fs::remove_file(&path).await.unwrap_or_default();
+		}
+
+		println!("[Magazines-Cache] Removed {count_removed} old {} entries from disk", self.name);
+	}
+}
+
+fn unix_now() -> u64 {
+	return SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::temp_dir::TempDir;
+
+	const TTL: Duration = Duration::from_secs(3600);
+
+	async fn lookup(cache: &Cache<Vec<u32>>, key: &str, value: Vec<u32>) -> CacheStatus {
+		return cache.get_or_fetch(key, TTL, async { Ok(value) }).await.unwrap().1;
+	}
+
+	#[tokio::test]
+	async fn evicts_least_recently_used_entries() {
+		let mut cache = Cache::new("test", None);
+		cache.max_entries = 2;
+
+		assert_eq!(lookup(&cache, "a", vec![1]).await, CacheStatus::Miss);
+		assert_eq!(lookup(&cache, "b", vec![2]).await, CacheStatus::Miss);
+		assert_eq!(lookup(&cache, "a", vec![1]).await, CacheStatus::Hit);
+		assert_eq!(lookup(&cache, "c", vec![3]).await, CacheStatus::Miss);
+
+		assert_eq!(cache.entries.lock().unwrap().len(), 2);
+		assert_eq!(lookup(&cache, "a", vec![1]).await, CacheStatus::Hit);
+		assert_eq!(lookup(&cache, "b", vec![2]).await, CacheStatus::Miss);
+	}
+
+	#[tokio::test]
+	async fn keeps_empty_results_briefly_and_not_on_disk() {
+		let dir = TempDir::new("magazines-cache");
+		let cache = Cache::new("test", Some(dir.path().to_path_buf()));
+
+		assert_eq!(lookup(&cache, "empty", vec![]).await, CacheStatus::Miss);
+		assert_eq!(lookup(&cache, "empty", vec![1]).await, CacheStatus::Hit);
+
+		let expires = cache.entries.lock().unwrap()["empty"].cell.get().unwrap().expires;
+		assert!(expires <= Instant::now() + EMPTY_TTL);
+		assert!(!fs::try_exists(dir.path().join("test/empty.json")).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn does_not_cache_errors() {
+		let cache: Cache<Vec<u32>> = Cache::new("test", None);
+
+		let failed = cache.get_or_fetch("key", TTL, async { Err(crate::error::generic_unavailable_error("unavailable")) }).await;
+		assert!(failed.is_err());
+		assert_eq!(lookup(&cache, "key", vec![1]).await, CacheStatus::Miss);
+	}
+
+	#[tokio::test]
+	async fn bounds_the_disk_copy() {
+		let dir = TempDir::new("magazines-cache");
+		let mut cache = Cache::new("test", Some(dir.path().to_path_buf()));
+		cache.max_disk_entries = 3;
+
+		for index in 0..5 {
+			lookup(&cache, &format!("key-{index}"), vec![index]).await;
+		}
+
+		let mut read_dir = fs::read_dir(dir.path().join("test")).await.unwrap();
+		let mut count = 0;
+		while read_dir.next_entry().await.unwrap().is_some() {
+			count += 1;
+		}
+		assert_eq!(count, 3);
+
+		// entries on disk are loaded without fetching
+		let restarted: Cache<Vec<u32>> = Cache::new("test", Some(dir.path().to_path_buf()));
+		assert_eq!(lookup(&restarted, "key-4", vec![]).await, CacheStatus::Hit);
+	}
+}
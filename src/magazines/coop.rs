@@ -0,0 +1,312 @@
+use axum::response::Response;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json;
+
+use crate::{
+	config,
+	error,
+	http_client::Upstream
+};
+use std::{
+	collections::HashMap,
+	env::var
+};
+
+use super::{date, EditionDefinition, Inlay, Page, Provider, Publication};
+
+const COOP_API: &str = "https://epaper.coopzeitung.ch/epaper/1.0";
+const COOP_EDITIONS: &str = "1134;Coopzeitung";
+
+// coopzeitung from the coop epaper api
+pub struct Coop {
+	upstream: Upstream,
+	editions: Vec<EditionDefinition>,
+}
+
+// requests to the coop epaper api
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EditionQuery {
+	def_id: u64,
+	publication_date: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FindEditions {
+	editions: Vec<EditionQuery>,
+	max_hits: u64,
+	start_date: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GetPages {
+	screen_info: ScreenInfo,
+	editions: Vec<EditionQuery>,
+}
+
+#[derive(Serialize)]
+struct ScreenInfo {
+	width: u32,
+	height: u32,
+}
+
+// responses of the coop epaper api
+#[derive(Deserialize)]
+struct CoopResponse<T> {
+	data: Option<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Edition {
+	#[serde(default)]
+	def_id: u64,
+	#[serde(default)]
+	pages: Vec<EditionPage>,
+	#[serde(default)]
+	inlays: Vec<Edition>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EditionPage {
+	#[serde(default)]
+	def_id: u64,
+	#[serde(default)]
+	edition_number: u64,
+	#[serde(default)]
+	edition_volume: u64,
+	#[serde(default)]
+	publication_date: String,
+}
+
+#[derive(Deserialize)]
+struct Pages {
+	#[serde(default)]
+	pages: Vec<CoopPage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CoopPage {
+	// resolution name (PREVIEW, THUMBNAIL, ...) to document
+	page_doc_url: HashMap<String, Document>,
+	// titles, article texts and other page data, the fields differ between editions
+	#[serde(flatten)]
+	details: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Document {
+	url: String,
+}
+
+impl Coop {
+	// edition definitions come from MAGAZINES_COOP_EDITIONS in the form of defId;name|defId;name
+	pub fn new(upstream: Upstream) -> Coop {
+		let list = var("MAGAZINES_COOP_EDITIONS").ok().filter(|value| !value.is_empty()).unwrap_or(COOP_EDITIONS.to_string());
+
+		let mut editions: Vec<EditionDefinition> = config::parse_list(&list).into_iter()
+			.filter(|(id, name)| match id.parse::<u64>() {
+				Ok(_) => true,
+				Err(_) => {
+					eprintln!("[Magazines-Coop] Ignoring edition {name}, {id:?} is not a defId");
+					false
+				}
+			})
+			.map(|(id, name)| EditionDefinition { id: id, name: name })
+			.collect();
+
+		if editions.is_empty() {
+			editions = config::parse_list(COOP_EDITIONS).into_iter().map(|(id, name)| EditionDefinition { id: id, name: name }).collect();
+		}
+
+		return Coop { upstream, editions };
+	}
+
+	async fn fetch(&self, method: &str, body: &impl Serialize) -> Result<String, Response> {
+		return self.upstream.post(format!("{COOP_API}/{method}"))
+			.json(body)
+			.send().await.map_err(|e| error::map_reqwest_error(e, "Magazines-Coop"))?
+			.error_for_status().map_err(|e| error::map_reqwest_error(e, "Magazines-Coop"))?
+			.text().await.map_err(|e| error::map_reqwest_error(e, "Magazines-Coop"));
+	}
+
+	async fn find_publications(&self, date: &str, amount: u64, edition: &str) -> Result<Vec<Publication>, Response> {
+		let query = FindEditions {
+			editions: vec![EditionQuery { def_id: def_id(edition)?, publication_date: date.to_string() }],
+			max_hits: amount,
+			start_date: date.to_string(),
+		};
+
+		let body = self.fetch("findEditionsFromDateWithInlays", &query).await?;
+		return parse_publications(&body).map_err(|e| error::map_serde_error(e, "Magazines-Coop"));
+	}
+
+	async fn find_pages(&self, date: &str, edition: &str) -> Result<Vec<Page>, Response> {
+		let query = GetPages {
+			screen_info: ScreenInfo { width: 1155, height: 1060 },
+			editions: vec![EditionQuery { def_id: def_id(edition)?, publication_date: date.to_string() }],
+		};
+
+		let body = self.fetch("getPages", &query).await?;
+		return parse_pages(&body).map_err(|e| error::map_serde_error(e, "Magazines-Coop"));
+	}
+
+	async fn find_week(&self, year: i32, week: u32, edition: &str) -> Result<Option<Publication>, Response> {
+		// week 1 can start in december and week 52/53 can end in january
+		let monday = date::iso_week_monday(year, week);
+		let first_day = date::format_date(monday);
+		let last_day = date::format_date(monday + 6);
+
+		// coop returns the editions published on or before the start date
+		let publications = self.find_publications(&last_day, 3, edition).await?;
+
+		let in_week = publications.iter()
+			.position(|publication| publication.publication_date >= first_day && publication.publication_date <= last_day)
+			.or_else(|| publications.iter().position(|publication| publication.edition_number == u64::from(week) && publication.edition_volume == year as u64));
+
+		return Ok(in_week.and_then(|index| publications.into_iter().nth(index)));
+	}
+}
+
+impl Provider for Coop {
+	fn default_resolution(&self) -> &'static str {
+		return "preview";
+	}
+
+	fn editions(&self) -> &[EditionDefinition] {
+		return &self.editions;
+	}
+
+	fn publications<'a>(&'a self, date: &'a str, amount: u64, edition: &'a str) -> BoxFuture<'a, Result<Vec<Publication>, Response>> {
+		return Box::pin(self.find_publications(date, amount, edition));
+	}
+
+	fn pages<'a>(&'a self, date: &'a str, edition: &'a str) -> BoxFuture<'a, Result<Vec<Page>, Response>> {
+		return Box::pin(self.find_pages(date, edition));
+	}
+
+	fn week<'a>(&'a self, year: i32, week: u32, edition: &'a str) -> BoxFuture<'a, Result<Option<Publication>, Response>> {
+		return Box::pin(self.find_week(year, week, edition));
+	}
+}
+
+// inlays of the coop api are editions of their own, any defId can be requested
+fn def_id(edition: &str) -> Result<u64, Response> {
+	return edition.parse().map_err(|_| error::generic_request_error(&format!("[Magazines-Coop] Edition {edition:?} is not a defId")));
+}
+
+fn parse_publications(body: &str) -> Result<Vec<Publication>, serde_json::Error> {
+	let response: CoopResponse<Vec<Edition>> = serde_json::from_str(body)?;
+
+	let publications = response.data.unwrap_or_default().into_iter()
+		.filter_map(|edition| {
+			let inlays = edition.inlays.iter()
+				.filter_map(|inlay| {
+					let page = inlay.pages.first();
+					let def_id = if inlay.def_id != 0 { inlay.def_id } else { page?.def_id };
+
+					if def_id == 0 {
+						return None;
+					}
+
+					return Some(Inlay {
+						edition: def_id.to_string(),
+						publication_date: page.map(|page| page.publication_date.clone()).unwrap_or_default(),
+					});
+				})
+				.collect();
+
+			let page = edition.pages.into_iter().next()?;
+
+			return Some(Publication {
+				edition_number: page.edition_number,
+				edition_volume: page.edition_volume,
+				publication_date: page.publication_date,
+				inlays: inlays,
+			});
+		})
+		.collect();
+
+	return Ok(publications);
+}
+
+fn parse_pages(body: &str) -> Result<Vec<Page>, serde_json::Error> {
+	let response: CoopResponse<Pages> = serde_json::from_str(body)?;
+
+	let pages = response.data.map(|data| data.pages).unwrap_or_default().into_iter()
+		.map(|page| Page {
+			resolutions: page.page_doc_url.into_iter()
+				.map(|(resolution, document)| (resolution.to_ascii_lowercase(), document.url))
+				.collect(),
+			text: page_text(&page.details),
+		})
+		.collect();
+
+	return Ok(pages);
+}
+
+// fields of the page data and its articles which hold readable text, ids, links and file names are left out
+const TEXT_FIELDS: [&str; 7] = ["title", "subtitle", "lead", "text", "caption", "sectionName", "headline"];
+
+fn page_text(details: &serde_json::Map<String, serde_json::Value>) -> String {
+	fn collect(key: &str, value: &serde_json::Value, text: &mut Vec<String>) {
+		match value {
+			serde_json::Value::String(value) if TEXT_FIELDS.contains(&key) && !value.trim().is_empty() => text.push(value.trim().to_string()),
+			serde_json::Value::Array(values) => values.iter().for_each(|value| collect(key, value, text)),
+			serde_json::Value::Object(values) => values.iter().for_each(|(key, value)| collect(key, value, text)),
+			_ => (),
+		}
+	}
+
+	let mut text = vec![];
+	details.iter().for_each(|(key, value)| collect(key, value, &mut text));
+
+	return text.join(" ");
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_synthetic_editions() {
+		let publications = parse_publications(include_str!("fixtures/coop_find_editions.json")).unwrap();
+
+		assert_eq!(publications.len(), 2);
+		assert_eq!(publications[0].edition_number, 5);
+		assert_eq!(publications[0].publication_date, "2024-01-30");
+		assert_eq!(publications[0].inlays.len(), 1);
+		assert_eq!(publications[0].inlays[0].edition, "1290");
+		assert_eq!(publications[0].inlays[0].publication_date, "2024-01-30");
+		assert!(publications[1].inlays.is_empty());
+	}
+
+	#[test]
+	fn parses_synthetic_pages() {
+		let pages = parse_pages(include_str!("fixtures/coop_get_pages.json")).unwrap();
+
+		assert_eq!(pages.len(), 2);
+		assert!(pages[0].resolutions["preview"].ends_with("page_1_preview.jpg"));
+		assert!(pages[0].resolutions["thumbnail"].ends_with("page_1_thumbnail.jpg"));
+		assert!(pages.iter().all(|page| page.text.is_empty()));
+	}
+
+	#[test]
+	fn extracts_page_text() {
+		let pages = parse_pages(include_str!("fixtures/coop_get_pages_articles.json")).unwrap();
+
+		assert_eq!(pages[0].text, "Editorial");
+
+		for expected in ["Genuss", "Das perfekte Fondue", "Käse reiben und langsam schmelzen."] {
+			assert!(pages[1].text.contains(expected), "{expected}");
+		}
+		for unexpected in ["b92e3d", "art-4711", "coop.ch", "fondue_header", "2"] {
+			assert!(!pages[1].text.contains(unexpected), "{unexpected}");
+		}
+	}
+}
@@ -0,0 +1,145 @@
+// calendar helpers on days since 1970-01-01 (proleptic gregorian calendar)
+
+pub fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year } as i64;
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let month = month as i64;
+	let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+	return era * 146097 + day_of_era - 719468;
+}
+
+pub fn civil_from_days(days: i64) -> (i32, u32, u32) {
+	let days = days + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month_index = (5 * day_of_year + 2) / 153;
+	let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+	let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+	let year = (year_of_era + era * 400) as i32 + if month <= 2 { 1 } else { 0 };
+
+	return (year, month, day);
+}
+
+// 0 = monday, 6 = sunday
+pub fn weekday(days: i64) -> i64 {
+	return (days + 3).rem_euclid(7);
+}
+
+pub fn iso_week_monday(year: i32, week: u32) -> i64 {
+	// january 4th is always in week 1
+	let january_4 = days_from_civil(year, 1, 4);
+	return january_4 - weekday(january_4) + (week as i64 - 1) * 7;
+}
+
+pub fn iso_weeks_in_year(year: i32) -> u32 {
+	// years starting on a thursday, and leap years starting on a wednesday, have 53 weeks
+	let january_1 = weekday(days_from_civil(year, 1, 1));
+	return if january_1 == 3 || (is_leap_year(year) && january_1 == 2) { 53 } else { 52 };
+}
+
+// the iso week belongs to the year of its thursday
+pub fn iso_week(days: i64) -> (i32, u32) {
+	let thursday = days - weekday(days) + 3;
+	let (year, _, _) = civil_from_days(thursday);
+	let week = (thursday - days_from_civil(year, 1, 1)) / 7 + 1;
+
+	return (year, week as u32);
+}
+
+pub fn is_leap_year(year: i32) -> bool {
+	return year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+}
+
+pub fn format_date(days: i64) -> String {
+	let (year, month, day) = civil_from_days(days);
+	return format!("{year:04}-{month:02}-{day:02}");
+}
+
+// parse dates in the form of YYYY-MM-DD
+pub fn parse_date(date: &str) -> Option<i64> {
+	let parts: Vec<&str> = date.split("-").collect();
+	let [year, month, day] = parts.as_slice() else {
+		return None;
+	};
+
+	if year.len() != 4 || month.len() != 2 || day.len() != 2 || !date.chars().all(|c| c.is_ascii_digit() || c == '-') {
+		return None;
+	}
+
+	let (Ok(year), Ok(month), Ok(day)) = (year.parse::<i32>(), month.parse::<u32>(), day.parse::<u32>()) else {
+		return None;
+	};
+
+	let days_in_month = match month {
+		1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+		4 | 6 | 9 | 11 => 30,
+		2 if is_leap_year(year) => 29,
+		2 => 28,
+		_ => return None,
+	};
+
+	if day == 0 || day > days_in_month {
+		return None;
+	}
+
+	return Some(days_from_civil(year, month, day));
+}
+
+pub fn today() -> i64 {
+	let seconds = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map(|duration| duration.as_secs())
+		.unwrap_or(0);
+
+	return (seconds / 86400) as i64;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn date(value: &str) -> i64 {
+		return parse_date(value).unwrap();
+	}
+
+	#[test]
+	fn handles_53_week_years() {
+		// 2020 is a leap year starting on a wednesday
+		assert_eq!(iso_weeks_in_year(2020), 53);
+		assert_eq!(format_date(iso_week_monday(2020, 53)), "2020-12-28");
+		assert_eq!(iso_week(date("2021-01-03")), (2020, 53));
+		assert_eq!(iso_week(date("2021-01-04")), (2021, 1));
+
+		// 2026 starts on a thursday
+		assert_eq!(iso_weeks_in_year(2026), 53);
+		assert_eq!(iso_weeks_in_year(2025), 52);
+		assert_eq!(iso_weeks_in_year(2027), 52);
+	}
+
+	#[test]
+	fn assigns_days_to_the_year_of_their_thursday() {
+		assert_eq!(format_date(iso_week_monday(2026, 1)), "2025-12-29");
+		assert_eq!(iso_week(date("2025-12-29")), (2026, 1));
+		assert_eq!(iso_week(date("2025-12-28")), (2025, 52));
+
+		assert_eq!(iso_week(date("2027-01-01")), (2026, 53));
+		assert_eq!(iso_week(date("2027-01-03")), (2026, 53));
+		assert_eq!(format_date(iso_week_monday(2026, 53)), "2026-12-28");
+		assert_eq!(format_date(iso_week_monday(2027, 1)), "2027-01-04");
+	}
+
+	#[test]
+	fn parses_dates() {
+		assert_eq!(format_date(date("2024-02-29")), "2024-02-29");
+		assert_eq!(weekday(date("1970-01-01")), 3);
+
+		for invalid in ["2023-02-29", "2024-13-01", "2024-00-10", "2024-1-01", "24-01-01", "2024-01-+1", "../2024-01-01"] {
+			assert_eq!(parse_date(invalid), None, "{invalid}");
+		}
+	}
+}
@@ -0,0 +1,122 @@
+use axum::{
+	extract::{Path, Query, State},
+	http::{StatusCode, header::CONTENT_TYPE},
+	response::{IntoResponse, Response}
+};
+use serde::Deserialize;
+
+use crate::{
+	client_ip::{ClientIp, RequestOrigin},
+	error
+};
+use super::{cached_publications, date, select_edition, Magazines, Publication, MAX_AMOUNT};
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+	#[serde(default = "default_amount")]
+	amount: u64,
+	edition: Option<String>,
+}
+
+fn default_amount() -> u64 {
+	return 10;
+}
+
+// atom feed of the latest editions, every entry links to the magazines page of its week
+pub async fn feed(
+	State(magazines): State<Magazines>,
+	client: ClientIp,
+	origin: RequestOrigin,
+	Path(provider): Path<String>,
+	Query(query): Query<FeedQuery>
+) -> Result<Response, Response> {
+	let source = magazines.provider(&provider)?;
+	let edition = select_edition(&source, query.edition)?;
+
+	if query.amount == 0 || query.amount > MAX_AMOUNT {
+		return Err(error::generic_request_error(&format!("[Magazines-Feed] amount has to be between 1 and {MAX_AMOUNT}")));
+	}
+
+	println!("[Magazines-Feed] {client} fetched {provider} feed");
+
+	let today = date::format_date(date::today());
+	let (publications, _) = cached_publications(&magazines, &source, &provider, edition.as_deref(), &today, query.amount).await?;
+
+	let base_url = match &magazines.public_url {
+		Some(public_url) => public_url.clone(),
+		None => origin.to_string(),
+	};
+
+	let body = atom(&base_url, &provider, edition.as_deref(), &publications);
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, "application/atom+xml; charset=utf-8")], body).into_response());
+}
+
+fn atom(base_url: &str, provider: &str, edition: Option<&str>, publications: &[Publication]) -> String {
+	let edition_query = edition.map(|edition| format!("edition={edition}"));
+	let feed_url = match &edition_query {
+		Some(edition_query) => format!("{base_url}/api/magazines/{provider}/feed?{edition_query}"),
+		None => format!("{base_url}/api/magazines/{provider}/feed"),
+	};
+
+	// publications are newest first, an empty feed is dated to the epoch
+	let updated = publications.first().map(|publication| publication.publication_date.as_str()).unwrap_or("1970-01-01");
+
+	let mut feed = format!(
+		"<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n\t<id>{}</id>\n\t<title>{} editions</title>\n\t<updated>{}T00:00:00Z</updated>\n\t<link rel=\"self\" href=\"{}\"/>\n\t<link href=\"{}\"/>\n\t<author><name>{}</name></author>\n",
+		escape(&feed_url),
+		escape(provider),
+		escape(updated),
+		escape(&feed_url),
+		escape(&format!("{base_url}/magazines/")),
+		escape(provider)
+	);
+
+	for publication in publications {
+		let Some(days) = date::parse_date(&publication.publication_date) else {
+			continue;
+		};
+
+		let (year, week) = date::iso_week(days);
+		let mut link = format!("{base_url}/magazines/?provider={provider}&year={year}&week={week}");
+		if let Some(edition_query) = &edition_query {
+			link.push_str(&format!("&{edition_query}"));
+		}
+
+		feed.push_str(&format!(
+			"\t<entry>\n\t\t<id>{}</id>\n\t\t<title>{} {}/{} ({})</title>\n\t\t<updated>{}T00:00:00Z</updated>\n\t\t<link href=\"{}\"/>\n\t</entry>\n",
+			escape(&link),
+			escape(provider),
+			publication.edition_number,
+			publication.edition_volume,
+			escape(&publication.publication_date),
+			escape(&publication.publication_date),
+			escape(&link)
+		));
+	}
+
+	feed.push_str("</feed>\n");
+	return feed;
+}
+
+fn escape(value: &str) -> String {
+	return value.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;");
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn links_the_selected_edition() {
+		let publications = [Publication { edition_number: 5, edition_volume: 2024, publication_date: "2024-01-30".to_string(), inlays: vec![] }];
+
+		let feed = atom("https://example.org", "coop", Some("1135"), &publications);
+		assert!(feed.contains("<link rel=\"self\" href=\"https://example.org/api/magazines/coop/feed?edition=1135\"/>"));
+		assert!(feed.contains("<link href=\"https://example.org/magazines/?provider=coop&amp;year=2024&amp;week=5&amp;edition=1135\"/>"));
+
+		let feed = atom("https://example.org", "coop", None, &publications);
+		assert!(feed.contains("<link rel=\"self\" href=\"https://example.org/api/magazines/coop/feed\"/>"));
+		assert!(!feed.contains("edition="));
+	}
+}
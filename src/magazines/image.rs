@@ -0,0 +1,232 @@
+use axum::{
+	body::{Body, Bytes},
+	extract::{Path, Query, State},
+	http::{HeaderName, HeaderValue, StatusCode, header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS}},
+	response::{IntoResponse, Response}
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::{
+	io,
+	path::PathBuf,
+	sync::atomic::{AtomicU64, Ordering}
+};
+use tokio::{fs, io::AsyncWriteExt, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+	client_ip::ClientIp,
+	error
+};
+use super::{cached_pages, date, select_edition, validate_date, Magazines, Page};
+
+const IMAGE_PATH: &str = "/api/magazines/image";
+// svg pages are served from our origin and must not run scripts
+const IMAGE_CSP: &str = "default-src 'none'; style-src 'unsafe-inline'; img-src data:";
+
+// unique names for partially written cache files
+static PART_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Deserialize)]
+pub struct ImageQuery {
+	pub resolution: Option<String>,
+	pub edition: Option<String>,
+}
+
+// urls of our image proxy for all pages of an edition, pages start at 1
+pub fn proxy_urls(provider: &str, edition: Option<&str>, publication_date: &str, amount: usize) -> Vec<String> {
+	let query = edition.map(|edition| format!("?edition={edition}")).unwrap_or_default();
+
+	return (1..=amount).map(|page| format!("{IMAGE_PATH}/{provider}/{publication_date}/{page}{query}")).collect();
+}
+
+// stream a page image through our server so clients never talk to the magazine cdn
+pub async fn image(
+	State(magazines): State<Magazines>,
+	client: ClientIp,
+	Path((provider, publication_date, page)): Path<(String, String, usize)>,
+	Query(query): Query<ImageQuery>
+) -> Result<Response, Response> {
+	let source = magazines.provider(&provider)?;
+	validate_date(&publication_date)?;
+
+	let resolution = query.resolution.unwrap_or_else(|| source.default_resolution().to_string()).to_ascii_lowercase();
+
+	if !valid_resolution(&resolution) {
+		return Err(error::generic_request_error("[Magazines-Image] Invalid resolution"));
+	}
+
+	let edition = select_edition(&source, query.edition)?;
+	let (pages, _) = cached_pages(&magazines, &source, &provider, edition.as_deref(), &publication_date).await?;
+
+	let Some(page_images) = page.checked_sub(1).and_then(|index| pages.get(index)) else {
+		return Ok((StatusCode::NOT_FOUND, format!("Page {page} does not exist")).into_response());
+	};
+
+	let Some(url) = page_images.resolutions.get(&resolution) else {
+		let available: Vec<&str> = page_images.resolutions.keys().map(|key| key.as_str()).collect();
+		return Ok((StatusCode::NOT_FOUND, format!("Resolution {resolution} does not exist, available: {}", available.join(", "))).into_response());
+	};
+
+	let max_age = magazines.ttl(date::parse_date(&publication_date).unwrap_or_default()).as_secs();
+	let cache_control = HeaderValue::from_str(&format!("public, max-age={max_age}")).unwrap_or(HeaderValue::from_static("no-cache"));
+	let cache_path = cache_path(&magazines, &provider, edition.as_deref(), &publication_date, page, &resolution);
+
+	if let Some(content) = read_stored(&magazines, &provider, edition.as_deref(), &publication_date, page, &resolution).await {
+		return Ok((StatusCode::OK, image_headers(HeaderValue::from_static(content_type(&content)), cache_control), content).into_response());
+	}
+
+	let url = magazines.allowed_image_url(url)?;
+
+	println!("[Magazines-Image] {client} fetched {provider} {publication_date} page {page} {resolution}");
+
+	let upstream = magazines.images.get(url)
+		.send().await.map_err(|e| error::map_reqwest_error(e, "Magazines-Image"))?
+		.error_for_status().map_err(|e| error::map_reqwest_error(e, "Magazines-Image"))?;
+
+	let upstream_type = upstream.headers().get(CONTENT_TYPE).cloned().unwrap_or(HeaderValue::from_static("application/octet-stream"));
+
+	let body = match cache_path {
+		Some(path) => Body::from_stream(stream_to_cache(upstream, path)),
+		None => Body::from_stream(upstream.bytes_stream()),
+	};
+
+	return Ok((StatusCode::OK, image_headers(upstream_type, cache_control), body).into_response());
+}
+
+fn image_headers(content_type: HeaderValue, cache_control: HeaderValue) -> [(HeaderName, HeaderValue); 4] {
+	return [
+		(CONTENT_TYPE, content_type),
+		(CACHE_CONTROL, cache_control),
+		(CONTENT_SECURITY_POLICY, HeaderValue::from_static(IMAGE_CSP)),
+		(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+	];
+}
+
+// load a whole page image from archive, cache or upstream
+// resolutions end up in the names of cache and archive files
+pub fn valid_resolution(resolution: &str) -> bool {
+	return resolution.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+}
+
+pub async fn load_image(magazines: &Magazines, provider: &str, edition: Option<&str>, publication_date: &str, page: usize, resolution: &str, page_images: &Page) -> Result<Vec<u8>, Response> {
+	if let Some(content) = read_stored(magazines, provider, edition, publication_date, page, resolution).await {
+		return Ok(content);
+	}
+
+	let Some(url) = page_images.resolutions.get(resolution) else {
+		return Err(error::generic_request_error(&format!("[Magazines-Image] Resolution {resolution} does not exist")));
+	};
+
+	let content = magazines.images.get(magazines.allowed_image_url(url)?)
+		.send().await.map_err(|e| error::map_reqwest_error(e, "Magazines-Image"))?
+		.error_for_status().map_err(|e| error::map_reqwest_error(e, "Magazines-Image"))?
+		.bytes().await.map_err(|e| error::map_reqwest_error(e, "Magazines-Image"))?;
+
+	if let Some(path) = cache_path(magazines, provider, edition, publication_date, page, resolution) {
+		if let Some(parent_folder) = path.parent() {
+			fs::create_dir_all(parent_folder).await.unwrap_or_default();
+		}
+		fs::write(&path, &content).await.unwrap_or_default();
+	}
+
+	return Ok(content.to_vec());
+}
+
+fn cache_path(magazines: &Magazines, provider: &str, edition: Option<&str>, publication_date: &str, page: usize, resolution: &str) -> Option<PathBuf> {
+	let file_name = match edition {
+		Some(edition) => format!("{edition}-{page}-{resolution}"),
+		None => format!("{page}-{resolution}"),
+	};
+
+	return magazines.cache_dir.as_ref()
+		.map(|dir| dir.join("images").join(provider).join(publication_date).join(file_name));
+}
+
+// archived images win over cached ones, only the default edition is archived
+async fn read_stored(magazines: &Magazines, provider: &str, edition: Option<&str>, publication_date: &str, page: usize, resolution: &str) -> Option<Vec<u8>> {
+	let archive_path = magazines.archive.as_ref()
+		.filter(|_| edition.is_none())
+		.and_then(|archive| archive.image_path(provider, publication_date, page, resolution));
+	let cache_path = cache_path(magazines, provider, edition, publication_date, page, resolution);
+
+	for path in archive_path.iter().chain(cache_path.iter()) {
+		if let Ok(content) = fs::read(path).await {
+			return Some(content);
+		}
+	}
+
+	return None;
+}
+
+// forward the upstream body to the client while writing it to the cache
+fn stream_to_cache(upstream: reqwest::Response, path: PathBuf) -> ReceiverStream<Result<Bytes, io::Error>> {
+	let (tx, rx) = mpsc::channel(16);
+
+	tokio::spawn(async move {
+		let part_path = path.with_extension(format!("part{}", PART_COUNTER.fetch_add(1, Ordering::Relaxed)));
+
+		if let Some(parent_folder) = path.parent() {
+			fs::create_dir_all(parent_folder).await.unwrap_or_default();
+		}
+
+		let mut file = fs::File::create(&part_path).await.ok();
+		let mut stream = upstream.bytes_stream();
+		let mut complete = true;
+
+		while let Some(chunk) = stream.next().await {
+			let chunk = match chunk {
+				Ok(chunk) => chunk,
+				Err(e) => {
+					eprintln!("[Magazines-Image] {e}");
+					complete = false;
+					let _ = tx.send(Err(io::Error::other(e))).await;
+					break;
+				}
+			};
+
+			// keep caching even when the client went away
+			if let Some(dest) = file.as_mut() && dest.write_all(&chunk).await.is_err() {
+				file = None;
+			}
+
+			let _ = tx.send(Ok(chunk)).await;
+		}
+
+		let cached = match file.as_mut() {
+			Some(dest) if complete => dest.flush().await.is_ok(),
+			_ => false,
+		};
+		drop(file);
+
+		if !cached {
+			fs::remove_file(&part_path).await.unwrap_or_default();
+		}
+		else if let Err(e) = fs::rename(&part_path, &path).await {
+			eprintln!("[Magazines-Image] {e}");
+		}
+	});
+
+	return ReceiverStream::new(rx);
+}
+
+// cached files are stored without headers, the type is detected from the content
+pub fn content_type(content: &[u8]) -> &'static str {
+	if content.starts_with(&[0xFF, 0xD8, 0xFF]) {
+		return "image/jpeg";
+	}
+	if content.starts_with(b"\x89PNG") {
+		return "image/png";
+	}
+	if content.starts_with(b"GIF8") {
+		return "image/gif";
+	}
+	if content.len() >= 12 && &content[0..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+		return "image/webp";
+	}
+	if content.starts_with(b"<svg") || content.starts_with(b"<?xml") {
+		return "image/svg+xml";
+	}
+
+	return "application/octet-stream";
+}
@@ -0,0 +1,421 @@
+use axum::{
+	extract::{Path, Query, State},
+	http::{HeaderName, HeaderValue, StatusCode, header::CONTENT_TYPE},
+	response::{IntoResponse, Response},
+	routing::{get, post},
+	Router
+};
+use futures_util::future::BoxFuture;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::{
+	collections::{BTreeMap, HashMap},
+	env::var,
+	path::PathBuf,
+	sync::Arc,
+	time::Duration
+};
+
+use crate::{
+	client_ip::ClientIp,
+	config,
+	error,
+	http_client::{HttpClient, Upstream}
+};
+
+mod archive;
+mod cache;
+mod coop;
+mod date;
+mod feed;
+mod image;
+mod pdf;
+mod search;
+
+use cache::{Cache, CacheStatus};
+
+const MAX_AMOUNT: u64 = 50;
+const CACHE_STATUS: HeaderName = HeaderName::from_static("x-cache");
+
+// a magazine source, every provider is served under /{provider}/...
+pub trait Provider: Send + Sync {
+	// resolution served when the client doesn't ask for one
+	fn default_resolution(&self) -> &'static str;
+
+	// regional and language editions, the first one is used when the client doesn't ask for one
+	fn editions(&self) -> &[EditionDefinition];
+
+	// editions published on or before date, newest first
+	fn publications<'a>(&'a self, date: &'a str, amount: u64, edition: &'a str) -> BoxFuture<'a, Result<Vec<Publication>, Response>>;
+
+	// pages of the edition published on date with all available image resolutions
+	fn pages<'a>(&'a self, date: &'a str, edition: &'a str) -> BoxFuture<'a, Result<Vec<Page>, Response>>;
+
+	// edition published in the iso week
+	fn week<'a>(&'a self, year: i32, week: u32, edition: &'a str) -> BoxFuture<'a, Result<Option<Publication>, Response>>;
+}
+
+fn default_edition(source: &Arc<dyn Provider>) -> &str {
+	return source.editions().first().map(|edition| edition.id.as_str()).unwrap_or_default();
+}
+
+// the default edition is passed around as None so its caches, archive and search index keep their keys
+fn select_edition(source: &Arc<dyn Provider>, edition: Option<String>) -> Result<Option<String>, Response> {
+	let Some(edition) = edition.filter(|edition| edition != default_edition(source)) else {
+		return Ok(None);
+	};
+
+	if edition.is_empty() || !edition.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+		return Err(error::generic_request_error(&format!("[Magazines] Invalid edition {edition:?}")));
+	}
+
+	return Ok(Some(edition));
+}
+
+#[derive(Clone)]
+struct Magazines {
+	providers: Arc<HashMap<&'static str, Arc<dyn Provider>>>,
+	publications: Arc<Cache<Vec<Publication>>>,
+	pages: Arc<Cache<Vec<Page>>>,
+	weeks: Arc<Cache<Option<Publication>>>,
+	// past editions never change, the current week can still be published or corrected
+	ttl_past: Duration,
+	ttl_current: Duration,
+	images: Upstream,
+	image_hosts: Arc<Vec<String>>,
+	cache_dir: Option<PathBuf>,
+	archive: Option<Arc<archive::Archive>>,
+	search: Arc<search::SearchIndex>,
+	// absolute base url for links in feeds, the request host is used without it
+	public_url: Option<String>,
+}
+
+impl Magazines {
+	fn provider(&self, name: &str) -> Result<Arc<dyn Provider>, Response> {
+		return self.providers.get(name).cloned()
+			.ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown provider {name}")).into_response());
+	}
+
+	// everything before monday of the current week is a past edition
+	fn ttl(&self, last_day: i64) -> Duration {
+		let today = date::today();
+		let current_monday = today - date::weekday(today);
+
+		return if last_day < current_monday { self.ttl_past } else { self.ttl_current };
+	}
+
+	// only https urls of hosts from the allow list are fetched
+	fn allowed_image_url(&self, url: &str) -> Result<Url, Response> {
+		let url = Url::parse(url).map_err(|e| error::generic_internal_error(&format!("[Magazines-Image] {url} {e}")))?;
+		let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+		let allowed = self.image_hosts.iter().any(|allowed| host == *allowed || host.ends_with(&format!(".{allowed}")));
+
+		if url.scheme() != "https" || !allowed {
+			return Err(error::generic_request_error(&format!("[Magazines-Image] Host {host} is not allowed")));
+		}
+
+		return Ok(url);
+	}
+}
+
+// requests from the frontend
+#[derive(Deserialize)]
+struct PublicationsRequest {
+	date: String,
+	#[serde(default = "default_amount")]
+	amount: u64,
+	edition: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PagesRequest {
+	date: String,
+	edition: Option<String>,
+}
+
+fn default_amount() -> u64 {
+	return 5;
+}
+
+// responses to the frontend
+#[derive(Clone, Serialize)]
+pub struct EditionDefinition {
+	pub id: String,
+	pub name: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Publication {
+	pub edition_number: u64,
+	pub edition_volume: u64,
+	pub publication_date: String,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub inlays: Vec<Inlay>,
+}
+
+// supplement published together with an edition, its pages are loaded with its own edition id
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Inlay {
+	pub edition: String,
+	pub publication_date: String,
+}
+
+// image urls of one page by resolution and the page text if the provider has any
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Page {
+	pub resolutions: BTreeMap<String, String>,
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub text: String,
+}
+
+// edition selected in the query of GET lookups
+#[derive(Deserialize)]
+struct EditionParam {
+	edition: Option<String>,
+}
+
+// configured editions and the inlays of the latest default edition, inlays can be selected like editions
+#[derive(Serialize)]
+struct Editions<'a> {
+	editions: &'a [EditionDefinition],
+	inlays: Vec<Inlay>,
+}
+
+#[derive(Serialize)]
+struct WeekEdition {
+	year: i32,
+	week: u32,
+	#[serde(flatten)]
+	publication: Publication,
+	pages: Vec<String>,
+	resolutions: Vec<String>,
+}
+
+pub fn router(http_client: &HttpClient) -> Option<Router> {
+	if !config::flag("MAGAZINES_ENABLED", true) {
+		println!("[Magazines] Disabled by MAGAZINES_ENABLED");
+		return None;
+	}
+
+	let mut providers: HashMap<&'static str, Arc<dyn Provider>> = HashMap::new();
+	providers.insert("coop", Arc::new(coop::Coop::new(http_client.upstream("coop"))));
+
+	let disk_dir = var("MAGAZINES_CACHE_DIR").ok().filter(|value| !value.is_empty()).map(PathBuf::from);
+
+	let state = Magazines {
+		providers: Arc::new(providers),
+		publications: Arc::new(Cache::new("publications", disk_dir.clone())),
+		pages: Arc::new(Cache::new("pages", disk_dir.clone())),
+		weeks: Arc::new(Cache::new("weeks", disk_dir.clone())),
+		ttl_past: Duration::from_secs(var("MAGAZINES_CACHE_TTL_PAST").ok().and_then(|value| value.parse().ok()).unwrap_or(30 * 24 * 3600)),
+		ttl_current: Duration::from_secs(var("MAGAZINES_CACHE_TTL_CURRENT").ok().and_then(|value| value.parse().ok()).unwrap_or(600)),
+		images: http_client.upstream("magazines_images"),
+		image_hosts: Arc::new(var("MAGAZINES_IMAGE_HOSTS").unwrap_or_else(|_| "coopzeitung.ch".to_string())
+			.split("|")
+			.map(|host| host.trim().to_ascii_lowercase())
+			.filter(|host| !host.is_empty())
+			.collect()),
+		search: Arc::new(search::SearchIndex::new(disk_dir.clone())),
+		cache_dir: disk_dir,
+		public_url: var("MAGAZINES_PUBLIC_URL").ok().filter(|value| !value.is_empty()).map(|value| value.trim_end_matches("/").to_string()),
+		archive: archive::Archive::from_env().map(Arc::new),
+	};
+
+	// the persisted index is restored before the archive adds its editions
+	let search_state = state.clone();
+	tokio::spawn(async move {
+		search_state.search.load().await;
+		archive::spawn(search_state);
+	});
+
+	return Some(Router::new()
+		// routes without provider are kept for older clients and always use coop
+		.route("/publications", post(coop_publications))
+		.route("/pages", post(coop_pages))
+		.route("/{provider}/editions", get(editions))
+		.route("/{provider}/publications", post(publications))
+		.route("/{provider}/pages", post(pages))
+		.route("/{provider}/feed", get(feed::feed))
+		.route("/{provider}/{year}/{week}", get(week_edition))
+		.route("/{provider}/{edition}/pdf", get(pdf::pdf))
+		.route("/image/{provider}/{date}/{page}", get(image::image))
+		.route("/archive", get(archive::list))
+		.route("/search", get(search::search))
+		.with_state(state));
+}
+
+async fn coop_publications(state: State<Magazines>, client: ClientIp, body: String) -> Result<Response, Response> {
+	return publications(state, client, Path("coop".to_string()), body).await;
+}
+
+async fn coop_pages(state: State<Magazines>, client: ClientIp, body: String) -> Result<Response, Response> {
+	return pages(state, client, Path("coop".to_string()), body).await;
+}
+
+async fn publications(State(magazines): State<Magazines>, client: ClientIp, Path(provider): Path<String>, body: String) -> Result<Response, Response> {
+	let source = magazines.provider(&provider)?;
+	let request: PublicationsRequest = serde_json::from_str(&body).map_err(|e| error::map_invalid_body_error(e, "Magazines"))?;
+	validate_date(&request.date)?;
+
+	if request.amount == 0 || request.amount > MAX_AMOUNT {
+		return Err(error::generic_request_error(&format!("[Magazines] amount has to be between 1 and {MAX_AMOUNT}")));
+	}
+
+	let edition = select_edition(&source, request.edition)?;
+
+	println!("[Magazines] {client} fetched {provider} publications");
+
+	let (response, status) = cached_publications(&magazines, &source, &provider, edition.as_deref(), &request.date, request.amount).await?;
+	return cached_json_response(&response, status);
+}
+
+async fn pages(State(magazines): State<Magazines>, client: ClientIp, Path(provider): Path<String>, body: String) -> Result<Response, Response> {
+	let source = magazines.provider(&provider)?;
+	let request: PagesRequest = serde_json::from_str(&body).map_err(|e| error::map_invalid_body_error(e, "Magazines"))?;
+	validate_date(&request.date)?;
+
+	let edition = select_edition(&source, request.edition)?;
+
+	println!("[Magazines] {client} fetched {provider} pages");
+
+	let (pages, status) = cached_pages(&magazines, &source, &provider, edition.as_deref(), &request.date).await?;
+	return cached_json_response(&image::proxy_urls(&provider, edition.as_deref(), &request.date, pages.len()), status);
+}
+
+async fn editions(State(magazines): State<Magazines>, Path(provider): Path<String>) -> Result<Response, Response> {
+	let source = magazines.provider(&provider)?;
+
+	// inlays change from week to week, the definitions are still useful without them
+	let today = date::format_date(date::today());
+	let inlays = match cached_publications(&magazines, &source, &provider, None, &today, 1).await {
+		Ok((publications, _)) => publications.into_iter().next().map(|publication| publication.inlays).unwrap_or_default(),
+		Err(_) => vec![],
+	};
+
+	return json_response(&Editions { editions: source.editions(), inlays: inlays });
+}
+
+// resolve an iso week to the edition published in it
+async fn week_edition(State(magazines): State<Magazines>, client: ClientIp, Path((provider, year, week)): Path<(String, i32, u32)>, Query(query): Query<EditionParam>) -> Result<Response, Response> {
+	let source = magazines.provider(&provider)?;
+	let edition = select_edition(&source, query.edition)?;
+
+	if !(1..=9999).contains(&year) || week == 0 || week > date::iso_weeks_in_year(year) {
+		return Err(error::generic_request_error(&format!("[Magazines] Week {week} does not exist in {year}")));
+	}
+
+	println!("[Magazines] {client} fetched {provider} week {year}-{week}");
+
+	let key = format!("{}/{year}/{week}", edition_key(&provider, edition.as_deref()));
+	let ttl = magazines.ttl(date::iso_week_monday(year, week) + 6);
+	let fetch = async {
+		let archive = magazines.archive.as_ref().filter(|_| edition.is_none());
+
+		match (source.week(year, week, edition.as_deref().unwrap_or(default_edition(&source))).await, archive) {
+			(Ok(Some(publication)), _) => Ok(Some(publication)),
+			(result, Some(archive)) => match archive.week(&provider, year, week).await {
+				Some(publication) => Ok(Some(publication)),
+				None => result,
+			},
+			(result, None) => result,
+		}
+	};
+	let (publication, week_status) = magazines.weeks.get_or_fetch(&key, ttl, fetch).await?;
+
+	let Some(publication) = publication else {
+		eprintln!("[Magazines] No {provider} edition found for week {year}-{week}");
+		return Ok((StatusCode::NOT_FOUND, "No edition found for this week").into_response());
+	};
+
+	let (pages, pages_status) = cached_pages(&magazines, &source, &provider, edition.as_deref(), &publication.publication_date).await?;
+
+	let resolutions = pages.first().map(|page| page.resolutions.keys().cloned().collect()).unwrap_or_default();
+	let urls = image::proxy_urls(&provider, edition.as_deref(), &publication.publication_date, pages.len());
+
+	return cached_json_response(&WeekEdition {
+		year: year,
+		week: week,
+		publication: publication,
+		pages: urls,
+		resolutions: resolutions,
+	}, week_status.and(pages_status));
+}
+
+async fn cached_publications(magazines: &Magazines, source: &Arc<dyn Provider>, provider: &str, edition: Option<&str>, publication_date: &str, amount: u64) -> Result<(Vec<Publication>, CacheStatus), Response> {
+	let key = format!("{}/{publication_date}/{amount}", edition_key(provider, edition));
+	let ttl = magazines.ttl(date::parse_date(publication_date).unwrap_or_default());
+	let fetch = async {
+		// only the default edition is archived
+		let archive = magazines.archive.as_ref().filter(|_| edition.is_none());
+
+		match (source.publications(publication_date, amount, edition.unwrap_or(default_edition(source))).await, archive) {
+			(Err(e), Some(archive)) => {
+				let archived = archive.publications(provider, publication_date, amount).await;
+				if archived.is_empty() { Err(e) } else { Ok(archived) }
+			},
+			// editions upstream dropped from its listing are filled in from the archive
+			(Ok(publications), Some(archive)) => Ok(archive::merge(publications, archive.publications(provider, publication_date, amount).await, amount)),
+			(result, None) => result,
+		}
+	};
+
+	return magazines.publications.get_or_fetch(&key, ttl, fetch).await;
+}
+
+async fn cached_pages(magazines: &Magazines, source: &Arc<dyn Provider>, provider: &str, edition: Option<&str>, publication_date: &str) -> Result<(Vec<Page>, CacheStatus), Response> {
+	let key = format!("{}/{publication_date}", edition_key(provider, edition));
+	let ttl = magazines.ttl(date::parse_date(publication_date).unwrap_or_default());
+
+	// editions which disappeared upstream are served from the archive
+	let fetch = async {
+		let archive = magazines.archive.as_ref().filter(|_| edition.is_none());
+
+		match (source.pages(publication_date, edition.unwrap_or(default_edition(source))).await, archive) {
+			(Ok(pages), _) if !pages.is_empty() => Ok(pages),
+			(result, Some(archive)) => match archive.edition(provider, publication_date).await {
+				Some(edition) => Ok(edition.pages),
+				None => result,
+			},
+			(result, None) => result,
+		}
+	};
+
+	let (pages, status) = magazines.pages.get_or_fetch(&key, ttl, fetch).await?;
+
+	// cached pages were indexed when they were fetched
+	if edition.is_none() && status == CacheStatus::Miss {
+		magazines.search.add(provider, publication_date, &pages).await;
+	}
+
+	return Ok((pages, status));
+}
+
+// cache key prefix, the default edition keeps the keys from before editions were selectable
+fn edition_key(provider: &str, edition: Option<&str>) -> String {
+	return match edition {
+		Some(edition) => format!("{provider}/{edition}"),
+		None => provider.to_string(),
+	};
+}
+
+fn json_response<T: Serialize>(value: &T) -> Result<Response, Response> {
+	let body = serde_json::to_string(value).map_err(|e| error::map_serde_error(e, "Magazines"))?;
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, "application/json")], body).into_response());
+}
+
+fn cached_json_response<T: Serialize>(value: &T, status: CacheStatus) -> Result<Response, Response> {
+	let mut response = json_response(value)?;
+	response.headers_mut().insert(CACHE_STATUS, HeaderValue::from_static(status.as_str()));
+
+	return Ok(response);
+}
+
+// dates are passed on to the providers and have to be in the form of YYYY-MM-DD
+fn validate_date(date: &str) -> Result<(), Response> {
+	match date::parse_date(date) {
+		Some(_) => return Ok(()),
+		None => return Err(error::generic_request_error(&format!("[Magazines] Invalid date {date:?}, expected YYYY-MM-DD"))),
+	}
+}
@@ -0,0 +1,344 @@
+use axum::{
+	body::{Body, Bytes},
+	extract::{Path, Query, State},
+	http::{HeaderValue, StatusCode, header::{CONTENT_DISPOSITION, CONTENT_TYPE}},
+	response::{IntoResponse, Response}
+};
+use std::io;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+	client_ip::ClientIp,
+	error
+};
+use super::{cached_pages, image, select_edition, validate_date, Magazines};
+
+// page width in points, the height follows the aspect ratio of the page image
+const PAGE_WIDTH: f64 = 595.0;
+
+// assemble all page images of an edition into one pdf which is streamed while pages are loaded
+pub async fn pdf(
+	State(magazines): State<Magazines>,
+	client: ClientIp,
+	// the edition is addressed by its publication date, the edition query selects a regional edition or inlay
+	Path((provider, publication_date)): Path<(String, String)>,
+	Query(query): Query<image::ImageQuery>
+) -> Result<Response, Response> {
+	let source = magazines.provider(&provider)?;
+	validate_date(&publication_date)?;
+
+	let resolution = query.resolution.unwrap_or_else(|| source.default_resolution().to_string()).to_ascii_lowercase();
+
+	if !image::valid_resolution(&resolution) {
+		return Err(error::generic_request_error("[Magazines-Pdf] Invalid resolution"));
+	}
+
+	let edition = select_edition(&source, query.edition)?;
+	let (pages, _) = cached_pages(&magazines, &source, &provider, edition.as_deref(), &publication_date).await?;
+
+	if pages.is_empty() {
+		return Ok((StatusCode::NOT_FOUND, "Edition has no pages").into_response());
+	}
+
+	// check the first page before the response starts, afterwards errors can't change the status anymore
+	let first_page = image::load_image(&magazines, &provider, edition.as_deref(), &publication_date, 1, &resolution, &pages[0]).await?;
+
+	if Jpeg::parse(&first_page).is_none() {
+		return Err(error::generic_unprocessable_error(&format!("[Magazines-Pdf] {provider} pages in {resolution} are not JPEG images")));
+	}
+
+	println!("[Magazines-Pdf] {client} exported {provider} {publication_date}");
+
+	let file_name = match &edition {
+		Some(edition) => format!("{provider}-{edition}-{publication_date}.pdf"),
+		None => format!("{provider}-{publication_date}.pdf"),
+	};
+	let disposition = HeaderValue::from_str(&format!("attachment; filename=\"{file_name}\""))
+		.unwrap_or(HeaderValue::from_static("attachment"));
+
+	let (tx, rx) = mpsc::channel::<Result<Bytes, io::Error>>(4);
+
+	tokio::spawn(async move {
+		let mut writer = PdfWriter::new(pages.len());
+		let mut first_page = Some(first_page);
+
+		if tx.send(Ok(writer.header())).await.is_err() {
+			return;
+		}
+
+		for (index, page) in pages.iter().enumerate() {
+			let content = match first_page.take() {
+				Some(content) => content,
+				None => match image::load_image(&magazines, &provider, edition.as_deref(), &publication_date, index + 1, &resolution, page).await {
+					Ok(content) => content,
+					Err(_) => {
+						eprintln!("[Magazines-Pdf] Skipping page {} of {provider} {publication_date}", index + 1);
+						continue;
+					}
+				},
+			};
+
+			let Some(jpeg) = Jpeg::parse(&content) else {
+				eprintln!("[Magazines-Pdf] Skipping page {} of {provider} {publication_date}, not a JPEG", index + 1);
+				continue;
+			};
+
+			// stop loading pages when the client went away
+			if tx.send(Ok(writer.page(&jpeg, content))).await.is_err() {
+				return;
+			}
+		}
+
+		let _ = tx.send(Ok(writer.trailer())).await;
+	});
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, HeaderValue::from_static("application/pdf")), (CONTENT_DISPOSITION, disposition)], Body::from_stream(ReceiverStream::new(rx))).into_response());
+}
+
+// size and color model from the start of frame segment
+struct Jpeg {
+	width: u32,
+	height: u32,
+	components: u8,
+}
+
+impl Jpeg {
+	fn parse(content: &[u8]) -> Option<Jpeg> {
+		if !content.starts_with(&[0xFF, 0xD8]) {
+			return None;
+		}
+
+		let mut position = 2;
+
+		while position + 4 <= content.len() {
+			if content[position] != 0xFF {
+				return None;
+			}
+
+			let marker = content[position + 1];
+			let length = u16::from_be_bytes([content[position + 2], content[position + 3]]) as usize;
+
+			// SOF0 - SOF15 without DHT, JPG and DAC
+			if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+				let segment = content.get(position + 4..position + 10)?;
+
+				return Some(Jpeg {
+					height: u16::from_be_bytes([segment[1], segment[2]]) as u32,
+					width: u16::from_be_bytes([segment[3], segment[4]]) as u32,
+					components: segment[5],
+				});
+			}
+
+			position += 2 + length;
+		}
+
+		return None;
+	}
+}
+
+// minimal pdf with one full page jpeg per page, objects are written as soon as a page is loaded
+struct PdfWriter {
+	offset: usize,
+	// byte offset of every object, index 0 is object 1
+	objects: Vec<usize>,
+	page_ids: Vec<usize>,
+}
+
+const CATALOG_ID: usize = 1;
+const PAGES_ID: usize = 2;
+
+impl PdfWriter {
+	fn new(pages: usize) -> PdfWriter {
+		return PdfWriter { offset: 0, objects: Vec::with_capacity(2 + pages * 3), page_ids: Vec::with_capacity(pages) };
+	}
+
+	fn header(&mut self) -> Bytes {
+		let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+		self.offset += out.len();
+
+		let catalog = format!("<< /Type /Catalog /Pages {PAGES_ID} 0 R >>");
+		self.object(&mut out, CATALOG_ID, catalog.as_bytes(), None);
+
+		return Bytes::from(out);
+	}
+
+	fn page(&mut self, jpeg: &Jpeg, content: Vec<u8>) -> Bytes {
+		let image_id = self.next_id();
+		let contents_id = image_id + 1;
+		let page_id = image_id + 2;
+		self.page_ids.push(page_id);
+
+		let (color_space, decode) = match jpeg.components {
+			1 => ("/DeviceGray", ""),
+			4 => ("/DeviceCMYK", " /Decode [1 0 1 0 1 0 1 0]"),
+			_ => ("/DeviceRGB", ""),
+		};
+
+		let height = PAGE_WIDTH * jpeg.height as f64 / jpeg.width.max(1) as f64;
+		let mut out = vec![];
+
+		let image = format!(
+			"<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {color_space}{decode} /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>",
+			jpeg.width, jpeg.height, content.len()
+		);
+		self.object(&mut out, image_id, image.as_bytes(), Some(&content));
+
+		let drawing = format!("q {PAGE_WIDTH:.2} 0 0 {height:.2} 0 0 cm /Im0 Do Q");
+		let contents = format!("<< /Length {} >>", drawing.len());
+		self.object(&mut out, contents_id, contents.as_bytes(), Some(drawing.as_bytes()));
+
+		let page = format!(
+			"<< /Type /Page /Parent {PAGES_ID} 0 R /MediaBox [0 0 {PAGE_WIDTH:.2} {height:.2}] /Resources << /XObject << /Im0 {image_id} 0 R >> >> /Contents {contents_id} 0 R >>"
+		);
+		self.object(&mut out, page_id, page.as_bytes(), None);
+
+		return Bytes::from(out);
+	}
+
+	// the page tree is written last because skipped pages are only known at the end
+	fn trailer(&mut self) -> Bytes {
+		let mut out = vec![];
+
+		let kids: Vec<String> = self.page_ids.iter().map(|id| format!("{id} 0 R")).collect();
+		let pages = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), self.page_ids.len());
+		self.object(&mut out, PAGES_ID, pages.as_bytes(), None);
+
+		let xref_offset = self.offset;
+		let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+
+		for offset in &self.objects {
+			xref.push_str(&format!("{offset:010} 00000 n \n"));
+		}
+
+		xref.push_str(&format!("trailer\n<< /Size {} /Root {CATALOG_ID} 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n", self.objects.len() + 1));
+		out.extend_from_slice(xref.as_bytes());
+		self.offset += xref.len();
+
+		return Bytes::from(out);
+	}
+
+	fn next_id(&self) -> usize {
+		// catalog and page tree are reserved
+		return self.objects.len().max(PAGES_ID) + 1;
+	}
+
+	fn object(&mut self, out: &mut Vec<u8>, id: usize, dictionary: &[u8], stream: Option<&[u8]>) {
+		if self.objects.len() < id {
+			self.objects.resize(id, 0);
+		}
+		self.objects[id - 1] = self.offset;
+
+		let start = out.len();
+		out.extend_from_slice(format!("{id} 0 obj\n").as_bytes());
+		out.extend_from_slice(dictionary);
+
+		if let Some(stream) = stream {
+			out.extend_from_slice(b"\nstream\n");
+			out.extend_from_slice(stream);
+			out.extend_from_slice(b"\nendstream");
+		}
+
+		out.extend_from_slice(b"\nendobj\n");
+		self.offset += out.len() - start;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// start of image, an app0 segment and a start of frame segment
+	fn jpeg(marker: u8, height: u16, width: u16, components: u8) -> Vec<u8> {
+		let mut content = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, b'J', b'F'];
+		content.extend_from_slice(&[0xFF, marker, 0x00, 8 + 3 * components, 8]);
+		content.extend_from_slice(&height.to_be_bytes());
+		content.extend_from_slice(&width.to_be_bytes());
+		content.push(components);
+		content.extend(std::iter::repeat_n(0x11, 3 * components as usize));
+		content.extend_from_slice(&[0xFF, 0xD9]);
+		return content;
+	}
+
+	#[test]
+	fn parses_start_of_frame() {
+		let baseline = Jpeg::parse(&jpeg(0xC0, 1200, 800, 3)).unwrap();
+		assert_eq!((baseline.width, baseline.height, baseline.components), (800, 1200, 3));
+
+		let progressive = Jpeg::parse(&jpeg(0xC2, 2000, 1414, 1)).unwrap();
+		assert_eq!((progressive.width, progressive.height, progressive.components), (1414, 2000, 1));
+
+		let cmyk = Jpeg::parse(&jpeg(0xC0, 10, 20, 4)).unwrap();
+		assert_eq!(cmyk.components, 4);
+	}
+
+	#[test]
+	fn skips_segments_which_are_no_frames() {
+		// a huffman table has a marker in the start of frame range
+		let mut content = vec![0xFF, 0xD8, 0xFF, 0xC4, 0x00, 0x04, 0x00, 0x00];
+		content.extend_from_slice(&jpeg(0xC1, 30, 40, 3)[2..]);
+
+		let jpeg = Jpeg::parse(&content).unwrap();
+		assert_eq!((jpeg.width, jpeg.height), (40, 30));
+	}
+
+	#[test]
+	fn rejects_invalid_jpegs() {
+		let complete = jpeg(0xC0, 1200, 800, 3);
+
+		// cut off inside the start of frame segment
+		assert!(Jpeg::parse(&complete[..14]).is_none());
+		// cut off before the start of frame segment
+		assert!(Jpeg::parse(&complete[..8]).is_none());
+		assert!(Jpeg::parse(&complete[2..]).is_none());
+		assert!(Jpeg::parse(b"\x89PNG\r\n\x1a\n").is_none());
+		assert!(Jpeg::parse(&[0xFF, 0xD8, 0x00, 0xC0, 0x00, 0x11]).is_none());
+	}
+
+	fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+		return haystack.windows(needle.len()).position(|window| window == needle);
+	}
+
+	#[test]
+	fn writes_valid_xref_offsets() {
+		let images = [jpeg(0xC0, 1200, 800, 3), jpeg(0xC2, 600, 800, 4)];
+		let mut writer = PdfWriter::new(images.len());
+		let mut pdf = writer.header().to_vec();
+
+		for image in &images {
+			pdf.extend_from_slice(&writer.page(&Jpeg::parse(image).unwrap(), image.clone()));
+		}
+		pdf.extend_from_slice(&writer.trailer());
+
+		assert_eq!(writer.offset, pdf.len());
+
+		let startxref = find(&pdf, b"startxref\n").unwrap();
+		let xref_offset: usize = String::from_utf8_lossy(&pdf[startxref + 10..]).lines().next().unwrap().parse().unwrap();
+		assert!(pdf[xref_offset..].starts_with(b"xref\n0 9\n"));
+
+		// catalog, page tree and image, contents and page per page
+		let xref = String::from_utf8_lossy(&pdf[xref_offset..]).to_string();
+		let entries: Vec<&str> = xref.lines().skip(3).take(8).collect();
+
+		for (index, entry) in entries.iter().enumerate() {
+			let offset: usize = entry[..10].parse().unwrap();
+			// entries are exactly 20 bytes including the line end
+			assert!(entry.len() == 19 && entry.ends_with(" 00000 n "), "{entry}");
+			assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", index + 1).as_bytes()), "object {}", index + 1);
+		}
+
+		assert!(find(&pdf, b"/Kids [5 0 R 8 0 R] /Count 2").is_some());
+		assert!(find(&pdf, b"/ColorSpace /DeviceCMYK /Decode [1 0 1 0 1 0 1 0]").is_some());
+		assert!(pdf.ends_with(b"%%EOF\n"));
+	}
+
+	#[test]
+	fn rejects_resolutions_which_are_no_file_names() {
+		assert!(image::valid_resolution("high_res"));
+		assert!(image::valid_resolution("1200"));
+
+		for resolution in ["../../etc", "a/b", "x.jpg", "hi res"] {
+			assert!(!image::valid_resolution(resolution), "{resolution:?} is rejected");
+		}
+	}
+}
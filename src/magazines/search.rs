@@ -0,0 +1,221 @@
+use axum::{
+	extract::{Query, State},
+	response::Response
+};
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	path::PathBuf,
+	sync::RwLock
+};
+use tokio::fs;
+
+use crate::{
+	client_ip::ClientIp,
+	error
+};
+use super::{json_response, Magazines, Page};
+
+const MAX_RESULTS: usize = 50;
+const INDEX_FILE: &str = "search.json";
+
+// inverted index over the text of all pages seen so far
+pub struct SearchIndex {
+	path: Option<PathBuf>,
+	index: RwLock<Index>,
+}
+
+#[derive(Default)]
+struct Index {
+	// page texts by "{provider}/{date}", this is what gets persisted
+	editions: HashMap<String, Vec<String>>,
+	// token to edition key and page number
+	tokens: BTreeMap<String, BTreeSet<(String, usize)>>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+	q: String,
+	provider: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+	provider: String,
+	publication_date: String,
+	pages: Vec<usize>,
+}
+
+impl SearchIndex {
+	pub fn new(cache_dir: Option<PathBuf>) -> SearchIndex {
+		return SearchIndex { path: cache_dir.map(|dir| dir.join(INDEX_FILE)), index: RwLock::new(Index::default()) };
+	}
+
+	// restore the index written by an earlier run
+	pub async fn load(&self) {
+		let Some(path) = &self.path else {
+			return;
+		};
+
+		let Ok(content) = fs::read(path).await else {
+			return;
+		};
+
+		match serde_json::from_slice::<HashMap<String, Vec<String>>>(&content) {
+			Ok(editions) => {
+				let mut index = self.index.write().unwrap();
+				for (key, texts) in editions {
+					index.insert(key, texts);
+				}
+				println!("[Magazines-Search] Loaded {} editions", index.editions.len());
+			},
+			Err(e) => eprintln!("[Magazines-Search] Could not read {}: {e}", path.display()),
+		}
+	}
+
+	// index the pages of an edition, editions without any text are skipped
+	pub async fn add(&self, provider: &str, publication_date: &str, pages: &[Page]) {
+		let key = format!("{provider}/{publication_date}");
+
+		if pages.iter().all(|page| page.text.is_empty()) || self.contains(&key, pages) {
+			return;
+		}
+
+		let texts: Vec<String> = pages.iter().map(|page| page.text.clone()).collect();
+
+		// serialize while holding the lock, write after releasing it
+		let content = {
+			let mut index = self.index.write().unwrap();
+
+			if index.editions.get(&key) == Some(&texts) {
+				return;
+			}
+
+			index.insert(key, texts);
+			self.path.as_ref().and_then(|_| serde_json::to_vec(&index.editions).ok())
+		};
+
+		if let (Some(path), Some(content)) = (&self.path, content) {
+			if let Some(parent_folder) = path.parent() {
+				fs::create_dir_all(parent_folder).await.unwrap_or_default();
+			}
+
+			let part = path.with_extension("json.part");
+			if let Err(e) = async { fs::write(&part, content).await?; fs::rename(&part, path).await }.await {
+				eprintln!("[Magazines-Search] Could not write {}: {e}", path.display());
+			}
+		}
+	}
+
+	fn contains(&self, key: &str, pages: &[Page]) -> bool {
+		let index = self.index.read().unwrap();
+
+		return index.editions.get(key).is_some_and(|texts| texts.len() == pages.len() && texts.iter().zip(pages).all(|(text, page)| *text == page.text));
+	}
+
+	// pages containing every term of the query, newest edition first
+	fn search(&self, query: &str, provider: Option<&str>) -> Vec<SearchResult> {
+		let terms = tokenize(query);
+		let index = self.index.read().unwrap();
+		let mut matches: Option<BTreeSet<(String, usize)>> = None;
+
+		for term in terms {
+			// terms match the start of words, "fondue" also finds "fonduerezept"
+			let found: BTreeSet<(String, usize)> = index.tokens.range(term.clone()..)
+				.take_while(|(token, _)| token.starts_with(&term))
+				.flat_map(|(_, pages)| pages.iter().cloned())
+				.collect();
+
+			matches = Some(match matches {
+				Some(previous) => previous.intersection(&found).cloned().collect(),
+				None => found,
+			});
+		}
+
+		let mut editions: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+		for (key, page) in matches.unwrap_or_default() {
+			editions.entry(key).or_default().push(page);
+		}
+
+		let mut results: Vec<SearchResult> = editions.into_iter()
+			.filter_map(|(key, pages)| {
+				let (provider, publication_date) = key.split_once("/")?;
+				return Some(SearchResult { provider: provider.to_string(), publication_date: publication_date.to_string(), pages: pages });
+			})
+			.filter(|result| provider.is_none_or(|provider| result.provider == provider))
+			.collect();
+
+		results.sort_by(|a, b| b.publication_date.cmp(&a.publication_date).then_with(|| a.provider.cmp(&b.provider)));
+		results.truncate(MAX_RESULTS);
+
+		return results;
+	}
+}
+
+impl Index {
+	fn insert(&mut self, key: String, texts: Vec<String>) {
+		// drop the tokens of an older version of this edition
+		if self.editions.contains_key(&key) {
+			self.tokens.values_mut().for_each(|pages| pages.retain(|(edition, _)| *edition != key));
+			self.tokens.retain(|_, pages| !pages.is_empty());
+		}
+
+		for (index, text) in texts.iter().enumerate() {
+			for token in tokenize(text) {
+				self.tokens.entry(token).or_default().insert((key.clone(), index + 1));
+			}
+		}
+
+		self.editions.insert(key, texts);
+	}
+}
+
+// lowercase words, single characters are too common to be useful
+fn tokenize(text: &str) -> BTreeSet<String> {
+	return text.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| word.chars().count() > 1)
+		.map(|word| word.to_lowercase())
+		.collect();
+}
+
+pub async fn search(State(magazines): State<Magazines>, client: ClientIp, Query(query): Query<SearchQuery>) -> Result<Response, Response> {
+	if tokenize(&query.q).is_empty() {
+		return Err(error::generic_request_error("[Magazines-Search] q needs at least one word with two or more characters"));
+	}
+
+	if let Some(provider) = &query.provider {
+		magazines.provider(provider)?;
+	}
+
+	println!("[Magazines-Search] {client} searched {:?}", query.q);
+
+	return json_response(&magazines.search.search(&query.q, query.provider.as_deref()));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn page(text: &str) -> Page {
+		return Page { resolutions: BTreeMap::new(), text: text.to_string() };
+	}
+
+	#[tokio::test]
+	async fn finds_pages_by_word_prefix() {
+		let index = SearchIndex::new(None);
+		index.add("coop", "2024-01-30", &[page("Editorial"), page("Das beste Fondue-Rezept"), page("Käse und Fonduerezepte")]).await;
+		index.add("coop", "2024-02-06", &[page("Fondue Chinoise")]).await;
+
+		let results = index.search("fondue", None);
+		assert_eq!(results.len(), 2);
+		assert_eq!(results[0].publication_date, "2024-02-06");
+		assert_eq!(results[1].pages, vec![2, 3]);
+
+		let results = index.search("KÄSE fondue", Some("coop"));
+		assert_eq!(results.len(), 1);
+		assert_eq!(results[0].pages, vec![3]);
+
+		assert!(index.search("fondue", Some("migros")).is_empty());
+	}
+}
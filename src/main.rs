@@ -1,3 +1,4 @@
+
 use axum::{
 	http::{StatusCode, Request},
 	routing::{get, any},
@@ -10,23 +11,77 @@ use tower_http::services::{
 	ServeDir,
 	ServeFile
 };
-use std;
+use std::{
+	env::var,
+	net::SocketAddr,
+	time::Duration
+};
+use tokio::{
+	signal,
+	sync::watch
+};
+
+use client_ip::ClientIp;
 
+#[cfg(feature = "magazines")]
 mod magazines;
+#[cfg(feature = "workflow")]
 mod workflow;
+#[cfg(feature = "infomaniakmail")]
+mod infomaniakmail;
+#[cfg(feature = "startpage")]
+mod startpage;
+#[cfg(feature = "plugins")]
+mod plugins;
+mod client_ip;
+#[cfg(any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "startpage", feature = "plugins"))]
+mod config;
+#[cfg(any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "startpage", feature = "plugins"))]
 mod error;
-// mod infomaniakmail;
+#[cfg(any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "startpage", feature = "plugins"))]
+mod http_client;
+#[cfg(any(feature = "magazines", feature = "infomaniakmail", feature = "startpage", feature = "plugins"))]
+mod rate_limit;
+#[cfg(all(test, any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "startpage")))]
+mod temp_dir;
 
 
 #[tokio::main]
 async fn main() {
-	let api = Router::new()
-		// .nest("/infomaniakmail", infomaniakmail::router().await)
-		.nest("/magazines", magazines::router())
-		.nest("/workflow", workflow::router().await)
+	#[cfg(any(feature = "magazines", feature = "workflow", feature = "infomaniakmail", feature = "startpage", feature = "plugins"))]
+	let http_client = http_client::HttpClient::from_env();
+
+	#[allow(unused_mut)]
+	let mut api = Router::new()
 		.route("/health", get(health))
 		.route("/test", any(test));
 
+	// optional modules are only mounted when compiled in and configured
+	#[cfg(feature = "infomaniakmail")]
+	if let Some(router) = infomaniakmail::router(&http_client).await {
+		api = api.nest("/infomaniakmail", rate_limit::layer(router, "infomaniakmail", "10/60"));
+	}
+
+	#[cfg(feature = "magazines")]
+	if let Some(router) = magazines::router(&http_client) {
+		api = api.nest("/magazines", rate_limit::layer(router, "magazines", "60/60"));
+	}
+
+	#[cfg(feature = "workflow")]
+	if let Some(router) = workflow::router(&http_client).await {
+		api = api.nest("/workflow", router);
+	}
+
+	#[cfg(feature = "startpage")]
+	if let Some(router) = startpage::router(&http_client) {
+		api = api.nest("/startpage", rate_limit::layer(router, "startpage", "120/60"));
+	}
+
+	#[cfg(feature = "plugins")]
+	if let Some(router) = plugins::router(&http_client) {
+		api = api.nest("/plugins", rate_limit::layer(router, "plugins", "60/60"));
+	}
+
 	let startpage = Router::new()
 		.fallback_service(ServeDir::new("static/startpage")
 		.fallback(ServeFile::new("static/startpage/index.html")));
@@ -40,15 +95,63 @@ async fn main() {
 		.nest("/api", api)
 		.merge(frontend);
 
-	let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 3000));
+	let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
 	let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-	axum::serve(listener, app).await.unwrap();
+
+	// stop accepting connections on SIGTERM/SIGINT and give in-flight work a deadline
+	let shutdown_timeout = Duration::from_secs(var("SHUTDOWN_TIMEOUT").ok().and_then(|value| value.parse().ok()).unwrap_or(30));
+	let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+
+	let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).with_graceful_shutdown(async move {
+		shutdown_signal().await;
+		let _ = shutdown_tx.send(true);
+	});
+
+	let drain = async {
+		if let Err(e) = server.await {
+			eprintln!("[Shutdown] {e}");
+		}
+		#[cfg(feature = "workflow")]
+		workflow::wait_for_jobs().await;
+	};
+
+	let deadline = async {
+		let _ = shutdown_rx.changed().await;
+		tokio::time::sleep(shutdown_timeout).await;
+	};
+
+	tokio::select! {
+		_ = drain => println!("[Shutdown] Finished all in-flight work"),
+		_ = deadline => eprintln!("[Shutdown] In-flight work did not finish within {}s, exiting", shutdown_timeout.as_secs()),
+	}
+}
+
+async fn shutdown_signal() {
+	let ctrl_c = async {
+		signal::ctrl_c().await.expect("[Shutdown] Failed to listen for SIGINT");
+	};
+
+	#[cfg(unix)]
+	let terminate = async {
+		signal::unix::signal(signal::unix::SignalKind::terminate())
+			.expect("[Shutdown] Failed to listen for SIGTERM")
+			.recv().await;
+	};
+
+	#[cfg(not(unix))]
+	let terminate = std::future::pending::<()>();
+
+	tokio::select! {
+		_ = ctrl_c => (),
+		_ = terminate => (),
+	}
+
+	println!("[Shutdown] Signal received, waiting for in-flight work");
 }
 
-async fn log_static(req: Request<Body>, next: middleware::Next) -> Response {
+async fn log_static(client: ClientIp, req: Request<Body>, next: middleware::Next) -> Response {
 	let path = req.uri().path().to_string();
 	let referrer = req.headers().get("User-Agent").and_then(|value| value.to_str().ok()).unwrap_or("Unknow User-Agent").to_string();
-	let client = req.headers().get("X-Forwarded-For").and_then(|value| value.to_str().ok()).unwrap_or("Unknow client").to_string();
 
 	let response = next.run(req).await;
 
@@ -0,0 +1,159 @@
+use axum::{
+	extract::Query,
+	http::{StatusCode, header::CONTENT_TYPE},
+	response::{IntoResponse, Response},
+	routing::get,
+	Router
+};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use serde_json;
+use std::{
+	collections::HashMap,
+	sync::Arc
+};
+
+use crate::{
+	client_ip::ClientIp,
+	config,
+	error,
+	http_client::HttpClient
+};
+
+mod weather;
+
+// a startpage widget with its own backend, served under /api/plugins/{name}/...
+pub trait Plugin: Send + Sync {
+	fn name(&self) -> &'static str;
+
+	// shown in the registry so the startpage can offer the widget
+	fn description(&self) -> &'static str;
+
+	// json rendered by the widget, options are the query parameters of the widget instance
+	fn widget<'a>(&'a self, options: &'a HashMap<String, String>) -> BoxFuture<'a, Result<serde_json::Value, Response>>;
+
+	// further routes of the plugin, /widget is added by the registry
+	fn routes(self: Arc<Self>) -> Router {
+		return Router::new();
+	}
+}
+
+// creates a plugin from its env vars, None when it can't be enabled
+type Constructor = fn(&HttpClient) -> Option<Arc<dyn Plugin>>;
+
+// every plugin compiled into the backend, PLUGINS decides which are enabled
+const AVAILABLE: [(&str, Constructor); 1] = [
+	("weather", weather::from_env),
+];
+
+#[derive(Serialize)]
+struct RegistryEntry {
+	name: &'static str,
+	description: &'static str,
+	widget: String,
+}
+
+pub fn router(http_client: &HttpClient) -> Option<Router> {
+	let mut env = config::RequiredEnv::new("Plugins");
+
+	let names = env.get("PLUGINS");
+
+	if !env.is_complete() {
+		return None;
+	}
+
+	let mut plugins = vec![];
+
+	for name in names.split("|").map(|name| name.trim()).filter(|name| !name.is_empty()) {
+		match AVAILABLE.iter().find(|(available, _)| *available == name) {
+			Some((_, constructor)) => match constructor(http_client) {
+				Some(plugin) => plugins.push(plugin),
+				None => eprintln!("[Plugins] {name} could not be enabled"),
+			},
+			None => eprintln!("[Plugins] Ignoring unknown plugin {name}"),
+		}
+	}
+
+	return Some(registry(plugins));
+}
+
+fn registry(plugins: Vec<Arc<dyn Plugin>>) -> Router {
+	let entries: Vec<RegistryEntry> = plugins.iter()
+		.map(|plugin| RegistryEntry { name: plugin.name(), description: plugin.description(), widget: format!("/api/plugins/{}/widget", plugin.name()) })
+		.collect();
+
+	let registry_body = Arc::new(serde_json::to_string(&entries).unwrap_or("[]".to_string()));
+	let mut router = Router::new().route("/", get(move || async move {
+		return (StatusCode::OK, [(CONTENT_TYPE, "application/json")], registry_body.to_string()).into_response();
+	}));
+
+	for plugin in plugins {
+		println!("[Plugins] Enabled {}", plugin.name());
+
+		let widget_plugin = plugin.clone();
+		let plugin_router = plugin.clone().routes()
+			.route("/widget", get(move |client: ClientIp, Query(options): Query<HashMap<String, String>>| widget(widget_plugin.clone(), client, options)));
+
+		router = router.nest(&format!("/{}", plugin.name()), plugin_router);
+	}
+
+	return router;
+}
+
+async fn widget(plugin: Arc<dyn Plugin>, client: ClientIp, options: HashMap<String, String>) -> Result<Response, Response> {
+	let payload = plugin.widget(&options).await?;
+	let body = serde_json::to_string(&payload).map_err(|e| error::map_serde_error(e, "Plugins"))?;
+
+	println!("[Plugins] {client} loaded widget {}", plugin.name());
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, "application/json")], body).into_response());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{body::{self, Body}, http::Request, routing::post};
+	use tower::ServiceExt;
+
+	struct Counter;
+
+	impl Plugin for Counter {
+		fn name(&self) -> &'static str {
+			return "counter";
+		}
+
+		fn description(&self) -> &'static str {
+			return "Counts to the given number";
+		}
+
+		fn widget<'a>(&'a self, options: &'a HashMap<String, String>) -> BoxFuture<'a, Result<serde_json::Value, Response>> {
+			return Box::pin(async move {
+				let to: u64 = options.get("to").and_then(|to| to.parse().ok()).ok_or_else(|| error::generic_request_error("to is required"))?;
+				return Ok(serde_json::json!((1..=to).collect::<Vec<_>>()));
+			});
+		}
+
+		fn routes(self: Arc<Self>) -> Router {
+			return Router::new().route("/reset", post(|| async { StatusCode::NO_CONTENT }));
+		}
+	}
+
+	async fn call(app: &Router, method: &str, uri: &str) -> (StatusCode, String) {
+		let response = app.clone().oneshot(Request::builder().method(method).uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+		let status = response.status();
+		let body = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+		return (status, String::from_utf8_lossy(&body).to_string());
+	}
+
+	#[tokio::test]
+	async fn serves_registry_widgets_and_plugin_routes() {
+		let app = registry(vec![Arc::new(Counter)]);
+
+		assert_eq!(call(&app, "GET", "/").await, (StatusCode::OK, r#"[{"name":"counter","description":"Counts to the given number","widget":"/api/plugins/counter/widget"}]"#.to_string()));
+		assert_eq!(call(&app, "GET", "/counter/widget?to=3").await, (StatusCode::OK, "[1,2,3]".to_string()));
+		assert_eq!(call(&app, "GET", "/counter/widget").await.0, StatusCode::BAD_REQUEST);
+		assert_eq!(call(&app, "POST", "/counter/reset").await.0, StatusCode::NO_CONTENT);
+		assert_eq!(call(&app, "GET", "/unknown/widget").await.0, StatusCode::NOT_FOUND);
+	}
+}
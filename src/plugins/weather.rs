@@ -0,0 +1,259 @@
+use axum::response::Response;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::{
+	collections::HashMap,
+	env::var,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant}
+};
+
+use crate::{
+	error,
+	http_client::{HttpClient, Upstream}
+};
+use super::Plugin;
+
+// open-meteo updates current conditions every 15 minutes
+const CACHE_DURATION: Duration = Duration::from_secs(10 * 60);
+const MAX_CACHED_LOCATIONS: usize = 100;
+
+// current weather from open-meteo, which needs no api key
+pub struct Weather {
+	upstream: Upstream,
+	api_url: String,
+	// latitude,longitude used when the widget doesn't set one
+	default_location: Option<(f64, f64)>,
+	cache: Mutex<HashMap<String, (Instant, serde_json::Value)>>,
+}
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+	current: Current,
+}
+
+#[derive(Deserialize)]
+struct Current {
+	time: String,
+	temperature_2m: f64,
+	apparent_temperature: f64,
+	weather_code: u32,
+	is_day: u8,
+	wind_speed_10m: f64,
+}
+
+#[derive(Serialize)]
+struct WeatherWidget {
+	latitude: f64,
+	longitude: f64,
+	time: String,
+	// degrees celsius
+	temperature: f64,
+	apparent_temperature: f64,
+	// km/h
+	wind_speed: f64,
+	weather_code: u32,
+	description: &'static str,
+	is_day: bool,
+}
+
+pub fn from_env(http_client: &HttpClient) -> Option<Arc<dyn Plugin>> {
+	let default_location = match var("WEATHER_LOCATION") {
+		Ok(location) if !location.is_empty() => match parse_location(&location) {
+			Some(location) => Some(location),
+			None => {
+				eprintln!("[Plugins-Weather] Invalid WEATHER_LOCATION {location:?}, expected latitude,longitude");
+				return None;
+			}
+		},
+		_ => None,
+	};
+
+	return Some(Arc::new(Weather {
+		upstream: http_client.upstream("weather"),
+		api_url: var("WEATHER_API_URL").ok().filter(|value| !value.is_empty()).unwrap_or("https://api.open-meteo.com/v1/forecast".to_string()),
+		default_location: default_location,
+		cache: Mutex::new(HashMap::new()),
+	}));
+}
+
+impl Plugin for Weather {
+	fn name(&self) -> &'static str {
+		return "weather";
+	}
+
+	fn description(&self) -> &'static str {
+		return "Current weather, set the location with latitude and longitude";
+	}
+
+	fn widget<'a>(&'a self, options: &'a HashMap<String, String>) -> BoxFuture<'a, Result<serde_json::Value, Response>> {
+		return Box::pin(async move {
+			let location = match (options.get("latitude"), options.get("longitude")) {
+				(Some(latitude), Some(longitude)) => parse_location(&format!("{latitude},{longitude}")),
+				_ => self.default_location,
+			};
+
+			let Some((latitude, longitude)) = location else {
+				return Err(error::generic_request_error("[Plugins-Weather] latitude and longitude are required"));
+			};
+
+			// rounded to about 1km so nearby widgets share the cache
+			let key = format!("{latitude:.2},{longitude:.2}");
+
+			if let Some((fetched, payload)) = self.cache.lock().unwrap().get(&key) && fetched.elapsed() < CACHE_DURATION {
+				return Ok(payload.clone());
+			}
+
+			let forecast: ForecastResponse = self.upstream.get(&self.api_url)
+				.query(&[
+					("latitude", format!("{latitude:.2}")),
+					("longitude", format!("{longitude:.2}")),
+					("current", "temperature_2m,apparent_temperature,weather_code,is_day,wind_speed_10m".to_string()),
+					("timezone", "auto".to_string()),
+				])
+				.send().await.map_err(|e| error::map_reqwest_error(e, "Plugins-Weather"))?
+				.error_for_status().map_err(|e| error::map_reqwest_error(e, "Plugins-Weather"))?
+				.json().await.map_err(|e| error::map_reqwest_error(e, "Plugins-Weather"))?;
+
+			let current = forecast.current;
+			let payload = serde_json::to_value(WeatherWidget {
+				latitude: latitude,
+				longitude: longitude,
+				time: current.time,
+				temperature: current.temperature_2m,
+				apparent_temperature: current.apparent_temperature,
+				wind_speed: current.wind_speed_10m,
+				weather_code: current.weather_code,
+				description: describe(current.weather_code),
+				is_day: current.is_day == 1,
+			}).map_err(|e| error::map_serde_error(e, "Plugins-Weather"))?;
+
+			let mut cache = self.cache.lock().unwrap();
+			cache.retain(|_, (fetched, _)| fetched.elapsed() < CACHE_DURATION);
+			if cache.len() < MAX_CACHED_LOCATIONS {
+				cache.insert(key, (Instant::now(), payload.clone()));
+			}
+
+			return Ok(payload);
+		});
+	}
+}
+
+fn parse_location(location: &str) -> Option<(f64, f64)> {
+	let (latitude, longitude) = location.split_once(",")?;
+	let latitude: f64 = latitude.trim().parse().ok()?;
+	let longitude: f64 = longitude.trim().parse().ok()?;
+
+	if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+		return None;
+	}
+
+	return Some((latitude, longitude));
+}
+
+// wmo weather interpretation codes used by open-meteo
+fn describe(weather_code: u32) -> &'static str {
+	return match weather_code {
+		0 => "Clear sky",
+		1 => "Mainly clear",
+		2 => "Partly cloudy",
+		3 => "Overcast",
+		45 | 48 => "Fog",
+		51 | 53 | 55 => "Drizzle",
+		56 | 57 => "Freezing drizzle",
+		61 | 63 | 65 => "Rain",
+		66 | 67 => "Freezing rain",
+		71 | 73 | 75 | 77 => "Snow",
+		80..=82 => "Rain showers",
+		85 | 86 => "Snow showers",
+		95 => "Thunderstorm",
+		96 | 99 => "Thunderstorm with hail",
+		_ => "Unknown",
+	};
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{extract::{Query, State}, http::StatusCode, routing::get, Router};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	// minimal stand-in for the open-meteo forecast endpoint
+	async fn forecast(State(requests): State<Arc<AtomicUsize>>, Query(query): Query<HashMap<String, String>>) -> String {
+		requests.fetch_add(1, Ordering::SeqCst);
+		let is_day = if query.get("latitude").map(String::as_str) == Some("47.38") { 1 } else { 0 };
+
+		return format!(r#"{{"latitude":{},"longitude":{},"current":{{"time":"2026-10-19T14:00","temperature_2m":12.4,"apparent_temperature":10.9,"weather_code":61,"is_day":{is_day},"wind_speed_10m":7.2}}}}"#, query["latitude"], query["longitude"]);
+	}
+
+	async fn weather(default_location: Option<(f64, f64)>) -> (Weather, Arc<AtomicUsize>) {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+		let requests = Arc::new(AtomicUsize::new(0));
+
+		let api = Router::new().route("/v1/forecast", get(forecast)).with_state(requests.clone());
+		tokio::spawn(async move { axum::serve(listener, api).await.unwrap() });
+
+		let weather = Weather {
+			upstream: HttpClient::from_env().upstream("weather"),
+			api_url: format!("http://{address}/v1/forecast"),
+			default_location: default_location,
+			cache: Mutex::new(HashMap::new()),
+		};
+
+		return (weather, requests);
+	}
+
+	fn options(latitude: &str, longitude: &str) -> HashMap<String, String> {
+		return HashMap::from([("latitude".to_string(), latitude.to_string()), ("longitude".to_string(), longitude.to_string())]);
+	}
+
+	#[test]
+	fn parses_locations_within_bounds() {
+		assert_eq!(parse_location("47.3769,8.5417"), Some((47.3769, 8.5417)));
+		assert_eq!(parse_location(" -90 , 180 "), Some((-90.0, 180.0)));
+		assert_eq!(parse_location("90,-180"), Some((90.0, -180.0)));
+
+		assert_eq!(parse_location("90.1,0"), None);
+		assert_eq!(parse_location("0,-180.1"), None);
+		assert_eq!(parse_location("NaN,0"), None);
+		assert_eq!(parse_location("47.3769"), None);
+		assert_eq!(parse_location("north,east"), None);
+		assert_eq!(parse_location(""), None);
+	}
+
+	#[tokio::test]
+	async fn builds_widget_from_upstream_response() {
+		let (weather, requests) = weather(Some((47.3769, 8.5417))).await;
+
+		let payload = weather.widget(&HashMap::new()).await.unwrap();
+		assert_eq!(payload, serde_json::json!({
+			"latitude": 47.3769,
+			"longitude": 8.5417,
+			"time": "2026-10-19T14:00",
+			"temperature": 12.4,
+			"apparent_temperature": 10.9,
+			"wind_speed": 7.2,
+			"weather_code": 61,
+			"description": "Rain",
+			"is_day": true,
+		}));
+
+		// nearby widgets are answered from the cache
+		assert_eq!(weather.widget(&options("47.3771", "8.5419")).await.unwrap()["is_day"], true);
+		assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+		assert_eq!(weather.widget(&options("-33.8688", "151.2093")).await.unwrap()["is_day"], false);
+		assert_eq!(requests.load(Ordering::SeqCst), 2);
+	}
+
+	#[tokio::test]
+	async fn rejects_missing_or_invalid_locations() {
+		let (weather, requests) = weather(None).await;
+
+		assert_eq!(weather.widget(&HashMap::new()).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+		assert_eq!(weather.widget(&options("91", "0")).await.unwrap_err().status(), StatusCode::BAD_REQUEST);
+		assert_eq!(requests.load(Ordering::SeqCst), 0);
+	}
+}
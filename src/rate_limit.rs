@@ -0,0 +1,217 @@
+use axum::{
+	Router,
+	body::Body,
+	extract::State,
+	http::{HeaderValue, Request, StatusCode, header::RETRY_AFTER},
+	middleware::{self, Next},
+	response::{IntoResponse, Response}
+};
+use std::{
+	collections::{HashMap, VecDeque, hash_map::Entry},
+	env::var,
+	net::{IpAddr, Ipv6Addr},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant}
+};
+
+use crate::{
+	client_ip::ClientIp,
+	config
+};
+
+// clients with a running window per route group, new clients wait for a free slot beyond this
+const MAX_CLIENTS: usize = 10_000;
+
+// fixed window limit per client ip for one route group
+#[derive(Clone)]
+pub struct RateLimit {
+	group: String,
+	max_requests: u32,
+	window: Duration,
+	max_clients: usize,
+	clients: Arc<Mutex<Clients>>,
+}
+
+#[derive(Default)]
+struct Clients {
+	windows: HashMap<IpAddr, Window>,
+	// start of every window in the order they started, ended windows are dropped from the front
+	starts: VecDeque<(Instant, IpAddr)>,
+}
+
+struct Window {
+	start: Instant,
+	count: u32,
+}
+
+impl RateLimit {
+	// limits are read from RATE_LIMITS in the form of group;requests/seconds, a limit of 0 disables it
+	pub fn from_env(group: &str, default: &str) -> Option<RateLimit> {
+		let limits = config::parse_map(&var("RATE_LIMITS").unwrap_or_default());
+		let limit = limits.get(group).map(|value| value.as_str()).unwrap_or(default);
+
+		let Some((max_requests, seconds)) = limit.split_once("/").and_then(|(requests, seconds)| {
+			Some((requests.trim().parse::<u32>().ok()?, seconds.trim().parse::<u64>().ok()?))
+		})
+		else {
+			eprintln!("[RateLimit] Invalid limit {limit} for {group}, not limiting");
+			return None;
+		};
+
+		if max_requests == 0 || seconds == 0 {
+			println!("[RateLimit] Disabled for {group}");
+			return None;
+		}
+
+		return Some(RateLimit::new(group, max_requests, Duration::from_secs(seconds)));
+	}
+
+	fn new(group: &str, max_requests: u32, window: Duration) -> RateLimit {
+		return RateLimit {
+			group: group.to_string(),
+			max_requests: max_requests,
+			window: window,
+			max_clients: MAX_CLIENTS,
+			clients: Arc::new(Mutex::new(Clients::default())),
+		};
+	}
+
+	// returns the seconds until the client may send requests again
+	fn check(&self, client: IpAddr) -> Result<(), u64> {
+		let now = Instant::now();
+		let client = client_key(client);
+		let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+		let Clients { windows, starts } = &mut *clients;
+
+		// windows end in the order they started, a client which started a new window has a later entry
+		while let Some((start, ip)) = starts.front().copied() && now.duration_since(start) >= self.window {
+			starts.pop_front();
+
+			if windows.get(&ip).is_some_and(|window| window.start == start) {
+				windows.remove(&ip);
+			}
+		}
+
+		let tracked = windows.len();
+		let window = match windows.entry(client) {
+			Entry::Occupied(entry) => entry.into_mut(),
+			// limited clients keep their windows, e.g. many spoofed or rotating addresses only make new clients wait
+			Entry::Vacant(_) if tracked >= self.max_clients => {
+				let oldest = starts.front().map(|(start, _)| *start).unwrap_or(now);
+				return Err(retry_after(self.window.saturating_sub(now.duration_since(oldest))));
+			},
+			Entry::Vacant(entry) => {
+				starts.push_back((now, client));
+				entry.insert(Window { start: now, count: 0 })
+			},
+		};
+
+		window.count += 1;
+
+		if window.count <= self.max_requests {
+			return Ok(());
+		}
+
+		if window.count == self.max_requests + 1 {
+			eprintln!("[RateLimit] {client} exceeded the limit for {}", self.group);
+		}
+
+		return Err(retry_after(self.window.saturating_sub(now.duration_since(window.start))));
+	}
+}
+
+// an ipv6 client usually gets a whole /64, every address in it counts as the same client
+fn client_key(ip: IpAddr) -> IpAddr {
+	return match ip.to_canonical() {
+		IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & (u128::MAX << 64))),
+		ip => ip,
+	};
+}
+
+// rounded up so clients retrying on time are not rejected again
+fn retry_after(remaining: Duration) -> u64 {
+	return (remaining.as_secs_f64().ceil() as u64).max(1);
+}
+
+pub fn layer(router: Router, group: &str, default: &str) -> Router {
+	return match RateLimit::from_env(group, default) {
+		Some(limit) => router.layer(middleware::from_fn_with_state(limit, limit_requests)),
+		None => router,
+	};
+}
+
+async fn limit_requests(State(limit): State<RateLimit>, ClientIp(client): ClientIp, req: Request<Body>, next: Next) -> Response {
+	if let Err(retry_after) = limit.check(client) {
+		let mut response = (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+		response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after));
+		return response;
+	}
+
+	return next.run(req).await;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::net::Ipv4Addr;
+
+	fn ip(index: u32) -> IpAddr {
+		return IpAddr::V4(Ipv4Addr::from(index));
+	}
+
+	#[test]
+	fn limits_each_client() {
+		let limit = RateLimit::new("test", 2, Duration::from_secs(60));
+
+		assert_eq!(limit.check(ip(1)), Ok(()));
+		assert_eq!(limit.check(ip(1)), Ok(()));
+		assert_eq!(limit.check(ip(1)), Err(60));
+		assert_eq!(limit.check(ip(2)), Ok(()));
+	}
+
+	#[test]
+	fn resets_after_the_window() {
+		let limit = RateLimit::new("test", 1, Duration::from_millis(20));
+
+		assert_eq!(limit.check(ip(1)), Ok(()));
+		assert_eq!(limit.check(ip(1)), Err(1));
+		std::thread::sleep(Duration::from_millis(30));
+		assert_eq!(limit.check(ip(1)), Ok(()));
+	}
+
+	#[test]
+	fn limits_ipv6_clients_by_prefix() {
+		let limit = RateLimit::new("test", 2, Duration::from_secs(60));
+
+		assert_eq!(limit.check("2001:db8:1:2::1".parse().unwrap()), Ok(()));
+		assert_eq!(limit.check("2001:db8:1:2:ffff::abcd".parse().unwrap()), Ok(()));
+		assert_eq!(limit.check("2001:db8:1:2::3".parse().unwrap()), Err(60));
+		assert_eq!(limit.check("2001:db8:1:3::1".parse().unwrap()), Ok(()));
+		// mapped addresses are the ipv4 client
+		assert_eq!(limit.check("::ffff:10.0.0.1".parse().unwrap()), Ok(()));
+		assert_eq!(limit.check(ip(0x0a000001)), Ok(()));
+		assert_eq!(limit.check(ip(0x0a000001)), Err(60));
+	}
+
+	#[test]
+	fn bounds_tracked_clients() {
+		let mut limit = RateLimit::new("test", 1, Duration::from_millis(50));
+		limit.max_clients = 3;
+
+		for index in 0..3 {
+			assert_eq!(limit.check(ip(index)), Ok(()));
+		}
+		assert_eq!(limit.check(ip(0)), Err(1));
+
+		// new clients wait for a free slot, limited clients stay limited
+		assert_eq!(limit.check(ip(3)), Err(1));
+		assert_eq!(limit.check(ip(0)), Err(1));
+
+		std::thread::sleep(Duration::from_millis(60));
+
+		assert_eq!(limit.check(ip(3)), Ok(()));
+		let clients = limit.clients.lock().unwrap();
+		assert_eq!(clients.windows.len(), 1);
+		assert_eq!(clients.starts.len(), 1);
+	}
+}
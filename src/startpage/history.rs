@@ -0,0 +1,335 @@
+use axum::{
+	extract::{Path, Query, State},
+	http::{HeaderMap, HeaderValue, StatusCode, header::{CONTENT_TYPE, ETAG}},
+	response::{IntoResponse, Response}
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{self, Value};
+use std::{
+	env::var,
+	path::PathBuf,
+	time::{SystemTime, UNIX_EPOCH}
+};
+use tokio::fs;
+
+use crate::{
+	client_ip::ClientIp,
+	error
+};
+use super::{etag, header_value, invalid_document, schema, Startpage};
+
+const HISTORY_DIR: &str = ".history";
+
+// every stored version of a profile, named after the unix time in milliseconds it was stored at
+pub struct History {
+	dir: PathBuf,
+	// revisions kept per profile, 0 keeps all
+	limit: usize,
+}
+
+#[derive(Serialize)]
+struct RevisionEntry {
+	revision: u64,
+	created_at: u64,
+	etag: String,
+	size: u64,
+	current: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+	from: u64,
+	// the current document when missing
+	to: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Change {
+	// json pointer to the changed value
+	path: String,
+	op: &'static str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	from: Option<Value>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	to: Option<Value>,
+}
+
+impl History {
+	pub fn from_env(dir: PathBuf) -> History {
+		return History {
+			dir: dir.join(HISTORY_DIR),
+			limit: var("STARTPAGE_HISTORY_LIMIT").ok().and_then(|value| value.parse().ok()).unwrap_or(50),
+		};
+	}
+
+	fn revision_path(&self, profile: &str, revision: u64) -> PathBuf {
+		return self.dir.join(profile).join(format!("{revision}.json"));
+	}
+
+	// oldest first
+	async fn revisions(&self, profile: &str) -> Vec<u64> {
+		let mut revisions = vec![];
+		let Ok(mut entries) = fs::read_dir(self.dir.join(profile)).await else {
+			return revisions;
+		};
+
+		while let Ok(Some(entry)) = entries.next_entry().await {
+			if let Some(revision) = entry.file_name().to_str().and_then(|name| name.strip_suffix(".json")).and_then(|name| name.parse().ok()) {
+				revisions.push(revision);
+			}
+		}
+
+		revisions.sort_unstable();
+		return revisions;
+	}
+
+	async fn read(&self, profile: &str, revision: u64) -> Option<Vec<u8>> {
+		return fs::read(self.revision_path(profile, revision)).await.ok();
+	}
+
+	// called with the write lock held, failures only cost history and don't fail the upload
+	pub async fn record(&self, profile: &str, previous: Option<&[u8]>, content: &[u8]) {
+		let mut revisions = self.revisions(profile).await;
+
+		// documents stored before the history existed
+		if let (true, Some(previous)) = (revisions.is_empty(), previous) {
+			let revision = now_millis().saturating_sub(1);
+			self.write(profile, revision, previous).await;
+			revisions.push(revision);
+		}
+
+		let revision = now_millis().max(revisions.last().map(|last| last + 1).unwrap_or_default());
+		self.write(profile, revision, content).await;
+		revisions.push(revision);
+
+		if self.limit > 0 && revisions.len() > self.limit {
+			for revision in &revisions[..revisions.len() - self.limit] {
+				fs::remove_file(self.revision_path(profile, *revision)).await.unwrap_or_default();
+			}
+		}
+	}
+
+	async fn write(&self, profile: &str, revision: u64, content: &[u8]) {
+		let path = self.revision_path(profile, revision);
+
+		let write = async {
+			fs::create_dir_all(self.dir.join(profile)).await?;
+			return fs::write(&path, content).await;
+		};
+
+		if let Err(e) = write.await {
+			eprintln!("[Startpage-History] Could not write {}: {e}", path.display());
+		}
+	}
+}
+
+pub async fn list(State(startpage): State<Startpage>, client: ClientIp, Path(profile): Path<String>, headers: HeaderMap) -> Result<Response, Response> {
+	startpage.authorize(&profile, &client, &headers)?;
+
+	let current = startpage.read(&profile).await?.map(|content| etag(&content));
+	let mut entries = vec![];
+
+	for revision in startpage.history.revisions(&profile).await.into_iter().rev() {
+		let Some(content) = startpage.history.read(&profile, revision).await else {
+			continue;
+		};
+
+		let etag = etag(&content);
+		entries.push(RevisionEntry { revision, created_at: revision / 1000, current: current.as_ref() == Some(&etag), etag: etag, size: content.len() as u64 });
+	}
+
+	return json_response(&entries);
+}
+
+pub async fn revision(State(startpage): State<Startpage>, client: ClientIp, Path((profile, revision)): Path<(String, u64)>, headers: HeaderMap) -> Result<Response, Response> {
+	startpage.authorize(&profile, &client, &headers)?;
+
+	let Some(content) = startpage.history.read(&profile, revision).await else {
+		return Ok((StatusCode::NOT_FOUND, format!("Revision {revision} does not exist")).into_response());
+	};
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, HeaderValue::from_static("application/json")), (ETAG, header_value(&etag(&content)))], content).into_response());
+}
+
+// restoring stores the old revision as a new one, it needs If-Match like every other write and is migrated like an upload
+pub async fn restore(State(startpage): State<Startpage>, client: ClientIp, Path((profile, revision)): Path<(String, u64)>, headers: HeaderMap) -> Result<Response, Response> {
+	startpage.authorize(&profile, &client, &headers)?;
+
+	let Some(content) = startpage.history.read(&profile, revision).await else {
+		return Ok((StatusCode::NOT_FOUND, format!("Revision {revision} does not exist")).into_response());
+	};
+
+	let document = schema::Document::parse(&content).map_err(|errors| invalid_document(&client, &profile, errors))?;
+
+	println!("[Startpage-History] {client} restores {profile} revision {revision}");
+
+	return startpage.replace(&profile, &client, &headers, &document.to_vec()).await;
+}
+
+pub async fn diff(State(startpage): State<Startpage>, client: ClientIp, Path(profile): Path<String>, Query(query): Query<DiffQuery>, headers: HeaderMap) -> Result<Response, Response> {
+	startpage.authorize(&profile, &client, &headers)?;
+
+	let Some(from) = startpage.history.read(&profile, query.from).await else {
+		return Ok((StatusCode::NOT_FOUND, format!("Revision {} does not exist", query.from)).into_response());
+	};
+
+	let to = match query.to {
+		Some(revision) => startpage.history.read(&profile, revision).await,
+		None => startpage.read(&profile).await?,
+	};
+
+	let Some(to) = to else {
+		return Ok((StatusCode::NOT_FOUND, "Revision does not exist").into_response());
+	};
+
+	let from: Value = serde_json::from_slice(&from).map_err(|e| error::map_serde_error(e, "Startpage-History"))?;
+	let to: Value = serde_json::from_slice(&to).map_err(|e| error::map_serde_error(e, "Startpage-History"))?;
+
+	let mut changes = vec![];
+	compare(String::new(), &from, &to, &mut changes);
+
+	return json_response(&changes);
+}
+
+// structural diff, arrays are compared by index
+fn compare(path: String, from: &Value, to: &Value, changes: &mut Vec<Change>) {
+	match (from, to) {
+		(Value::Object(from), Value::Object(to)) => {
+			for (key, from_value) in from {
+				let key_path = format!("{path}/{}", escape_pointer(key));
+				match to.get(key) {
+					Some(to_value) => compare(key_path, from_value, to_value, changes),
+					None => changes.push(Change { path: key_path, op: "removed", from: Some(from_value.clone()), to: None }),
+				}
+			}
+
+			for (key, to_value) in to.iter().filter(|(key, _)| !from.contains_key(*key)) {
+				changes.push(Change { path: format!("{path}/{}", escape_pointer(key)), op: "added", from: None, to: Some(to_value.clone()) });
+			}
+		},
+		(Value::Array(from), Value::Array(to)) => {
+			for index in 0..from.len().max(to.len()) {
+				let index_path = format!("{path}/{index}");
+				match (from.get(index), to.get(index)) {
+					(Some(from_value), Some(to_value)) => compare(index_path, from_value, to_value, changes),
+					(Some(from_value), None) => changes.push(Change { path: index_path, op: "removed", from: Some(from_value.clone()), to: None }),
+					(None, Some(to_value)) => changes.push(Change { path: index_path, op: "added", from: None, to: Some(to_value.clone()) }),
+					(None, None) => (),
+				}
+			}
+		},
+		(from, to) if from != to => changes.push(Change { path: path, op: "changed", from: Some(from.clone()), to: Some(to.clone()) }),
+		_ => (),
+	}
+}
+
+fn escape_pointer(key: &str) -> String {
+	return key.replace("~", "~0").replace("/", "~1");
+}
+
+fn json_response<T: Serialize>(value: &T) -> Result<Response, Response> {
+	let body = serde_json::to_string(value).map_err(|e| error::map_serde_error(e, "Startpage-History"))?;
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, "application/json")], body).into_response());
+}
+
+fn now_millis() -> u64 {
+	return SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_millis() as u64).unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::http::header::{IF_MATCH, IF_NONE_MATCH};
+	use std::sync::Arc;
+	use super::super::tests::{etag_of, send, state, CHANGED_DOCUMENT, DOCUMENT, PROFILE};
+
+	#[tokio::test]
+	async fn lists_and_diffs_revisions() {
+		let dir = crate::temp_dir::TempDir::new("startpage-history");
+		let state = state(dir.path());
+
+		let (_, headers, _) = send(&state, "PUT", "", &[(IF_NONE_MATCH, "*")], DOCUMENT).await;
+		send(&state, "PUT", "", &[(IF_MATCH, &etag_of(&headers))], CHANGED_DOCUMENT).await;
+
+		let (status, _, body) = send(&state, "GET", "/revisions", &[], "").await;
+		let entries: Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(entries.as_array().map(|entries| entries.len()), Some(2));
+		// newest first, only the stored document is current
+		assert_eq!(entries[0]["current"], true);
+		assert_eq!(entries[1]["current"], false);
+
+		let (status, _, body) = send(&state, "GET", &format!("/diff?from={}", entries[1]["revision"]), &[], "").await;
+		let changes: Value = serde_json::from_str(&body).unwrap();
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(changes, serde_json::json!([
+			{ "path": "/elements/0", "op": "removed", "from": serde_json::from_str::<Value>(DOCUMENT).unwrap()["elements"][0] },
+			{ "path": "/style/backgroundColor", "op": "changed", "from": "#131319", "to": "#000000" },
+		]));
+
+		let (status, _, _) = send(&state, "GET", "/diff?from=1", &[], "").await;
+		assert_eq!(status, StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn restores_old_revisions() {
+		let dir = crate::temp_dir::TempDir::new("startpage-history");
+		let state = state(dir.path());
+
+		let (_, headers, _) = send(&state, "PUT", "", &[(IF_NONE_MATCH, "*")], DOCUMENT).await;
+		let (_, headers, _) = send(&state, "PUT", "", &[(IF_MATCH, &etag_of(&headers))], CHANGED_DOCUMENT).await;
+		let first = state.history.revisions(PROFILE).await[0];
+
+		assert_eq!(send(&state, "POST", &format!("/revisions/{first}/restore"), &[], "").await.0, StatusCode::PRECONDITION_REQUIRED);
+
+		let (status, _, _) = send(&state, "POST", &format!("/revisions/{first}/restore"), &[(IF_MATCH, &etag_of(&headers))], "").await;
+		assert_eq!(status, StatusCode::NO_CONTENT);
+
+		let (_, _, content) = send(&state, "GET", "", &[], "").await;
+		assert_eq!(serde_json::from_str::<Value>(&content).unwrap(), serde_json::from_str::<Value>(DOCUMENT).unwrap());
+		// the restore is a new revision, the old ones are kept
+		assert_eq!(state.history.revisions(PROFILE).await.len(), 3);
+	}
+
+	#[tokio::test]
+	async fn rejects_restoring_invalid_revisions() {
+		let dir = crate::temp_dir::TempDir::new("startpage-history");
+		let state = state(dir.path());
+
+		let (_, headers, _) = send(&state, "PUT", "", &[(IF_NONE_MATCH, "*")], DOCUMENT).await;
+		let current = etag_of(&headers);
+		// written by a frontend which had elements the schema doesn't know
+		state.history.write(PROFILE, 1, br##"{"version":1,"style":{"backgroundColor":"#131319","foregroundColor":"#2d2d38"},"elements":[{"type":"Clock"}]}"##).await;
+
+		let (status, _, body) = send(&state, "POST", "/revisions/1/restore", &[(IF_MATCH, &current)], "").await;
+		assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+		assert!(body.contains("/elements/0/type"));
+
+		let (_, headers, _) = send(&state, "GET", "", &[], "").await;
+		assert_eq!(etag_of(&headers), current);
+	}
+
+	#[tokio::test]
+	async fn prunes_old_revisions() {
+		let dir = crate::temp_dir::TempDir::new("startpage-history");
+		let mut state = state(dir.path());
+		state.history = Arc::new(History { dir: dir.path().join(HISTORY_DIR), limit: 2 });
+
+		// stored before the history existed, recorded with the first upload
+		fs::write(state.document_path(PROFILE), DOCUMENT).await.unwrap();
+		let (_, headers, _) = send(&state, "GET", "", &[], "").await;
+		let (_, headers, _) = send(&state, "PUT", "", &[(IF_MATCH, &etag_of(&headers))], CHANGED_DOCUMENT).await;
+
+		let revisions = state.history.revisions(PROFILE).await;
+		assert_eq!(revisions.len(), 2);
+		assert_eq!(state.history.read(PROFILE, revisions[0]).await.as_deref(), Some(DOCUMENT.as_bytes()));
+
+		send(&state, "PUT", "", &[(IF_MATCH, &etag_of(&headers))], DOCUMENT).await;
+
+		let pruned = state.history.revisions(PROFILE).await;
+		assert_eq!(pruned.len(), 2);
+		assert!(!pruned.contains(&revisions[0]));
+		assert_eq!(pruned[0], revisions[1]);
+	}
+}
@@ -0,0 +1,426 @@
+use axum::{
+	extract::{Query, State},
+	http::{StatusCode, header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE}},
+	response::{IntoResponse, Response}
+};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+	collections::HashMap,
+	env::var,
+	path::{Path, PathBuf},
+	sync::Mutex,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+use tokio::fs;
+
+use crate::{
+	client_ip::ClientIp,
+	error,
+	http_client::{self, HttpClient, Upstream}
+};
+use super::Startpage;
+
+const ICON_DIR: &str = ".icons";
+const ICON_SIZE: u32 = 64;
+const MAX_PAGE_SIZE: usize = 1024 * 1024;
+const MAX_ICON_SIZE: usize = 512 * 1024;
+// candidates tried per site before giving up
+const MAX_CANDIDATES: usize = 4;
+// sites without icon are not fetched again for a while, every shortcut of a dead site asks on each load
+const FAILURE_CACHE_DURATION: Duration = Duration::from_secs(10 * 60);
+const MAX_FAILURES: usize = 1000;
+// the endpoint needs no token and every origin gets a file, so the disk copy is bounded
+const MAX_DISK_ENTRIES: usize = 10_000;
+
+// icons of linked sites, resolved once per origin and kept as fixed size svg
+pub struct Icons {
+	upstream: Upstream,
+	dir: PathBuf,
+	max_age: Duration,
+	// origins whose last resolve failed
+	failures: Mutex<HashMap<String, Instant>>,
+	max_disk_entries: usize,
+}
+
+#[derive(Deserialize)]
+pub struct IconQuery {
+	url: String,
+}
+
+// <link> of the site pointing to an icon
+struct Candidate {
+	url: Url,
+	score: u32,
+}
+
+impl Icons {
+	pub fn from_env(http_client: &HttpClient, dir: PathBuf) -> Icons {
+		return Icons {
+			upstream: http_client.public_upstream("startpage_icons"),
+			dir: dir.join(ICON_DIR),
+			max_age: Duration::from_secs(var("STARTPAGE_ICON_MAX_AGE").ok().and_then(|value| value.parse().ok()).unwrap_or(7 * 24 * 3600)),
+			failures: Mutex::new(HashMap::new()),
+			max_disk_entries: MAX_DISK_ENTRIES,
+		};
+	}
+
+	async fn download(&self, url: Url, max_size: usize) -> Result<(Url, Vec<u8>), String> {
+		let mut response = self.upstream.get(url).send().await
+			.and_then(|response| response.error_for_status())
+			.map_err(|e| e.to_string())?;
+
+		if response.content_length().is_some_and(|length| length as usize > max_size) {
+			return Err(format!("{} is larger than {max_size} bytes", response.url()));
+		}
+
+		let url = response.url().clone();
+		let mut content = vec![];
+
+		while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+			content.extend_from_slice(&chunk);
+
+			if content.len() > max_size {
+				return Err(format!("{url} is larger than {max_size} bytes"));
+			}
+		}
+
+		return Ok((url, content));
+	}
+
+	async fn store(&self, origin: &Url, path: &Path, svg: &[u8]) {
+		let part = path.with_extension("svg.part");
+		let write = async {
+			fs::create_dir_all(&self.dir).await?;
+			fs::write(&part, svg).await?;
+			return fs::rename(&part, path).await;
+		};
+
+		if let Err(e) = write.await {
+			eprintln!("[Startpage-Icon] Could not cache icon of {origin}: {e}");
+		}
+
+		self.prune().await;
+	}
+
+	// drops the least recently resolved icons beyond max_disk_entries
+	async fn prune(&self) {
+		let Ok(mut read_dir) = fs::read_dir(&self.dir).await else {
+			return;
+		};
+
+		let mut files = vec![];
+		while let Ok(Some(file)) = read_dir.next_entry().await {
+			let modified = file.metadata().await.and_then(|metadata| metadata.modified()).unwrap_or(UNIX_EPOCH);
+			files.push((modified, file.path()));
+		}
+
+		if files.len() <= self.max_disk_entries {
+			return;
+		}
+
+		files.sort();
+		let count_removed = files.len() - self.max_disk_entries;
+
+		for (_, path) in files.into_iter().take(count_removed) {
+			fs::remove_file(&path).await.unwrap_or_default();
+		}
+
+		println!("[Startpage-Icon] Removed {count_removed} old icons from disk");
+	}
+
+	async fn resolve(&self, origin: &Url) -> Result<Vec<u8>, String> {
+		if self.failures.lock().unwrap().get(origin.as_str()).is_some_and(|failed| failed.elapsed() < FAILURE_CACHE_DURATION) {
+			return Err(format!("{origin} has no usable icon, failed recently"));
+		}
+
+		let resolved = self.fetch(origin).await;
+
+		let mut failures = self.failures.lock().unwrap();
+		failures.retain(|_, failed| failed.elapsed() < FAILURE_CACHE_DURATION);
+
+		match resolved {
+			Ok(_) => failures.remove(origin.as_str()),
+			Err(_) if failures.len() < MAX_FAILURES => failures.insert(origin.to_string(), Instant::now()),
+			Err(_) => None,
+		};
+
+		return resolved;
+	}
+
+	// icons linked on the start page of the site, /favicon.ico as last resort
+	async fn fetch(&self, origin: &Url) -> Result<Vec<u8>, String> {
+		let mut candidates = match self.download(origin.clone(), MAX_PAGE_SIZE).await {
+			Ok((url, page)) => link_candidates(&url, &String::from_utf8_lossy(&page)),
+			Err(e) => {
+				eprintln!("[Startpage-Icon] Could not load {origin}: {e}");
+				vec![]
+			}
+		};
+
+		candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.score));
+		candidates.truncate(MAX_CANDIDATES - 1);
+
+		if let Ok(favicon) = origin.join("/favicon.ico") {
+			candidates.push(Candidate { url: favicon, score: 0 });
+		}
+
+		for candidate in candidates.into_iter().filter(|candidate| http_client::is_public_url(&candidate.url)) {
+			match self.download(candidate.url.clone(), MAX_ICON_SIZE).await {
+				Ok((_, content)) => match image_type(&content) {
+					Some(mime) => return Ok(wrap_svg(mime, &content)),
+					None => eprintln!("[Startpage-Icon] {} is not an image", candidate.url),
+				},
+				Err(e) => eprintln!("[Startpage-Icon] Could not load {}: {e}", candidate.url),
+			}
+		}
+
+		return Err(format!("{origin} has no usable icon"));
+	}
+}
+
+// not authenticated, images can't send a bearer
+pub async fn icon(State(startpage): State<Startpage>, client: ClientIp, Query(query): Query<IconQuery>) -> Result<Response, Response> {
+	let url = Url::parse(&query.url).map_err(|e| error::generic_request_error(&format!("[Startpage-Icon] {client} Invalid url {:?}: {e}", query.url)))?;
+
+	if !http_client::is_public_url(&url) {
+		return Err(error::generic_request_error(&format!("[Startpage-Icon] {client} Only public http(s) urls are allowed")));
+	}
+
+	let icons = &startpage.icons;
+	let origin = Url::parse(&url.origin().ascii_serialization()).map_err(|e| error::generic_request_error(&format!("[Startpage-Icon] Invalid origin of {url}: {e}")))?;
+	let path = icons.dir.join(format!("{}.svg", hex::encode(Sha256::digest(origin.as_str()))));
+
+	let modified = fs::metadata(&path).await.and_then(|metadata| metadata.modified()).ok();
+	let fresh = modified.is_some_and(|modified| SystemTime::now().duration_since(modified).unwrap_or_default() < icons.max_age);
+
+	if fresh && let Ok(svg) = fs::read(&path).await {
+		return Ok(svg_response(svg));
+	}
+
+	match icons.resolve(&origin).await {
+		Ok(svg) => {
+			println!("[Startpage-Icon] {client} resolved icon of {origin}");
+
+			icons.store(&origin, &path, &svg).await;
+
+			return Ok(svg_response(svg));
+		},
+		// an outdated icon is better than none while the site is unreachable
+		Err(e) => match fs::read(&path).await {
+			Ok(svg) => return Ok(svg_response(svg)),
+			Err(_) => {
+				eprintln!("[Startpage-Icon] {e}");
+				return Ok((StatusCode::NOT_FOUND, e).into_response());
+			}
+		},
+	}
+}
+
+fn svg_response(svg: Vec<u8>) -> Response {
+	return (StatusCode::OK, [
+		(CONTENT_TYPE, "image/svg+xml"),
+		(CACHE_CONTROL, "public, max-age=86400"),
+		(CONTENT_SECURITY_POLICY, "default-src 'none'; img-src data:; style-src 'unsafe-inline'"),
+	], svg).into_response();
+}
+
+// svg icons scale best, then the largest declared size, touch icons are usually 180px
+fn link_candidates(page_url: &Url, html: &str) -> Vec<Candidate> {
+	let lowercase = html.to_ascii_lowercase();
+	let mut candidates = vec![];
+	let mut position = 0;
+
+	while let Some(start) = lowercase[position..].find("<link").map(|start| start + position) {
+		let end = lowercase[start..].find(">").map(|end| end + start).unwrap_or(lowercase.len());
+		position = end;
+
+		let attributes = attributes(&html[start + 5..end]);
+		let attribute = |name: &str| attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str()).unwrap_or_default();
+
+		let rel = attribute("rel").to_ascii_lowercase();
+		let rel: Vec<&str> = rel.split_whitespace().collect();
+		let Some(url) = Some(attribute("href")).filter(|href| !href.is_empty()).and_then(|href| page_url.join(&href.replace("&amp;", "&")).ok()) else {
+			continue;
+		};
+
+		let size = attribute("sizes").split_whitespace()
+			.filter_map(|size| size.to_ascii_lowercase().split_once("x").and_then(|(width, _)| width.parse::<u32>().ok()))
+			.max();
+
+		let svg = attribute("type").eq_ignore_ascii_case("image/svg+xml") || url.path().to_ascii_lowercase().ends_with(".svg") || attribute("sizes").eq_ignore_ascii_case("any");
+
+		let score = match (rel.contains(&"apple-touch-icon") || rel.contains(&"apple-touch-icon-precomposed"), rel.contains(&"icon")) {
+			(_, true) if svg => 1000,
+			(true, _) => size.unwrap_or(180),
+			(_, true) => size.unwrap_or(16),
+			_ => continue,
+		};
+
+		candidates.push(Candidate { url: url, score: score });
+	}
+
+	return candidates;
+}
+
+// name="value", name='value' and name=value pairs of a tag
+pub fn attributes(tag: &str) -> Vec<(String, String)> {
+	let mut attributes = vec![];
+	let mut chars = tag.chars().peekable();
+
+	loop {
+		while chars.next_if(|c| c.is_whitespace() || *c == '/').is_some() {}
+
+		let name: String = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '=' && *c != '/')).collect();
+		if name.is_empty() {
+			return attributes;
+		}
+
+		while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+		let mut value = String::new();
+		if chars.next_if_eq(&'=').is_some() {
+			while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+			match chars.next_if(|c| *c == '"' || *c == '\'') {
+				Some(quote) => value = std::iter::from_fn(|| chars.next_if(|c| *c != quote)).collect(),
+				None => value = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect(),
+			}
+			chars.next_if(|c| *c == '"' || *c == '\'');
+		}
+
+		attributes.push((name.to_ascii_lowercase(), value));
+	}
+}
+
+// content types are often wrong for icons, the bytes decide
+fn image_type(content: &[u8]) -> Option<&'static str> {
+	if content.starts_with(b"\x89PNG") {
+		return Some("image/png");
+	}
+	if content.starts_with(&[0, 0, 1, 0]) {
+		return Some("image/x-icon");
+	}
+	if content.starts_with(&[0xff, 0xd8, 0xff]) {
+		return Some("image/jpeg");
+	}
+	if content.starts_with(b"GIF8") {
+		return Some("image/gif");
+	}
+	if content.len() > 12 && content.starts_with(b"RIFF") && &content[8..12] == b"WEBP" {
+		return Some("image/webp");
+	}
+
+	// html pages with inline svgs are no icons
+	let start = String::from_utf8_lossy(&content[..content.len().min(1024)]).to_ascii_lowercase();
+	let start = start.trim_start_matches('\u{feff}').trim_start();
+	if start.starts_with("<svg") || (start.starts_with("<?xml") && start.contains("<svg")) {
+		return Some("image/svg+xml");
+	}
+
+	return None;
+}
+
+// scripts of embedded svgs don't run inside <image>, every icon ends up the same size
+fn wrap_svg(mime: &str, content: &[u8]) -> Vec<u8> {
+	return format!(
+		r#"<svg xmlns="http://www.w3.org/2000/svg" width="{ICON_SIZE}" height="{ICON_SIZE}" viewBox="0 0 {ICON_SIZE} {ICON_SIZE}"><image href="data:{mime};base64,{}" width="{ICON_SIZE}" height="{ICON_SIZE}" preserveAspectRatio="xMidYMid meet"/></svg>"#,
+		base64(content)
+	).into_bytes();
+}
+
+fn base64(content: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut encoded = String::with_capacity(content.len().div_ceil(3) * 4);
+
+	for chunk in content.chunks(3) {
+		let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+		let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+		for index in 0..4 {
+			match index <= chunk.len() {
+				true => encoded.push(ALPHABET[(value >> (18 - index * 6) & 0x3f) as usize] as char),
+				false => encoded.push('='),
+			}
+		}
+	}
+
+	return encoded;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{Router, routing::any};
+	use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+	#[test]
+	fn prefers_scalable_and_large_icons() {
+		let page = Url::parse("https://example.com/start/").unwrap();
+		let html = r#"<head><LINK rel="shortcut icon" href="/favicon.png" sizes="32x32"><link rel=apple-touch-icon href='touch.png'><link rel="stylesheet" href="a.css"><link rel="icon" type="image/svg+xml" href="https://cdn.example.com/i.svg?a=1&amp;b=2"/></head>"#;
+
+		let mut candidates = link_candidates(&page, html);
+		candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.score));
+
+		let urls: Vec<&str> = candidates.iter().map(|candidate| candidate.url.as_str()).collect();
+		assert_eq!(urls, vec!["https://cdn.example.com/i.svg?a=1&b=2", "https://example.com/start/touch.png", "https://example.com/favicon.png"]);
+	}
+
+	#[test]
+	fn detects_images_by_content() {
+		assert_eq!(image_type(b"\x89PNG\r\n"), Some("image/png"));
+		assert_eq!(image_type(b"\xef\xbb\xbf  <svg xmlns=\"http://www.w3.org/2000/svg\"/>"), Some("image/svg+xml"));
+		assert_eq!(image_type(b"<?xml version=\"1.0\"?>\n<!DOCTYPE svg>\n<svg/>"), Some("image/svg+xml"));
+		assert_eq!(image_type(b"<?xml version=\"1.0\"?><error>not found</error>"), None);
+		assert_eq!(image_type(b"<!DOCTYPE html><html><body><svg></svg></body></html>"), None);
+	}
+
+	#[tokio::test]
+	async fn remembers_failed_resolves() {
+		let requests = Arc::new(AtomicUsize::new(0));
+		let counter = requests.clone();
+
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let origin = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+		let site = Router::new().fallback(any(move || async move {
+			counter.fetch_add(1, Ordering::SeqCst);
+			return (StatusCode::NOT_FOUND, "gone");
+		}));
+		tokio::spawn(async move { axum::serve(listener, site).await.unwrap() });
+
+		let icons = Icons { upstream: Upstream::unrestricted(), dir: PathBuf::new(), max_age: Duration::ZERO, failures: Mutex::new(HashMap::new()), max_disk_entries: 0 };
+
+		assert!(icons.resolve(&origin).await.is_err());
+		let fetched = requests.load(Ordering::SeqCst);
+		assert!(fetched > 0);
+
+		assert!(icons.resolve(&origin).await.unwrap_err().ends_with("failed recently"));
+		assert_eq!(requests.load(Ordering::SeqCst), fetched);
+	}
+
+	#[tokio::test]
+	async fn prunes_cached_icons() {
+		let dir = crate::temp_dir::TempDir::new("startpage-icons");
+		let icons = Icons { upstream: Upstream::unrestricted(), dir: dir.path().to_path_buf(), max_age: Duration::ZERO, failures: Mutex::new(HashMap::new()), max_disk_entries: 2 };
+
+		for (index, name) in ["oldest", "older", "newest"].iter().enumerate() {
+			let path = dir.path().join(format!("{name}.svg"));
+			icons.store(&Url::parse("https://example.com").unwrap(), &path, b"<svg/>").await;
+			std::fs::File::options().write(true).open(&path).unwrap().set_modified(SystemTime::now() - Duration::from_secs(100 - index as u64)).unwrap();
+		}
+		icons.prune().await;
+
+		assert!(!dir.path().join("oldest.svg").exists());
+		assert!(dir.path().join("older.svg").exists());
+		assert!(dir.path().join("newest.svg").exists());
+	}
+
+	#[test]
+	fn encodes_base64() {
+		assert_eq!(base64(b""), "");
+		assert_eq!(base64(b"f"), "Zg==");
+		assert_eq!(base64(b"fo"), "Zm8=");
+		assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+	}
+}
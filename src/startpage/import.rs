@@ -0,0 +1,264 @@
+use axum::{
+	http::{StatusCode, header::CONTENT_TYPE},
+	response::{IntoResponse, Response}
+};
+use serde_json::{self, Value};
+
+use crate::{
+	client_ip::ClientIp,
+	error
+};
+use super::{icon, invalid_document, schema};
+
+// exports with embedded icons get large
+pub const MAX_IMPORT_SIZE: usize = 16 * 1024 * 1024;
+const MAX_LINKS: usize = 5000;
+
+// bookmark folder with links, folders are only created once they get their first link
+struct Folder {
+	name: String,
+	links: Vec<schema::Shortcut>,
+}
+
+// folders in the order of their first link, the link count is kept to stop at MAX_LINKS
+#[derive(Default)]
+struct Folders {
+	folders: Vec<Folder>,
+	links: usize,
+}
+
+// browser exports are converted into a document, the client stores it with PUT like any other edit
+pub async fn import(client: ClientIp, body: String) -> Result<Response, Response> {
+	let trimmed = body.trim_start_matches('\u{feff}').trim_start();
+
+	let folders = if trimmed.starts_with("{") {
+		let backup: Value = serde_json::from_str(trimmed).map_err(|e| error::map_invalid_body_error(e, "Startpage-Import"))?;
+		let mut folders = Folders::default();
+		firefox_folder(&backup, &mut folders);
+		folders
+	}
+	else if trimmed.to_ascii_lowercase().contains("<dl") {
+		netscape_folders(trimmed)
+	}
+	else {
+		return Err(error::generic_request_error("[Startpage-Import] Expected a bookmark HTML export or a Firefox JSON backup"));
+	};
+
+	let links = folders.links;
+	let elements: Vec<schema::Element> = folders.folders.into_iter()
+		.map(|folder| schema::Element::Shortcut(schema::ShortcutContainer { name: folder.name, styles: schema::ShortcutStyles::default(), content: folder.links }))
+		.collect();
+
+	if links == 0 {
+		return Err(error::generic_unprocessable_error("[Startpage-Import] The export contains no http(s) links"));
+	}
+
+	println!("[Startpage-Import] {client} imported {links} links in {} groups", elements.len());
+
+	let document = schema::Document { version: schema::CURRENT_VERSION, style: schema::Style::default(), elements: elements };
+	// validated like an upload, exports are shared and must not smuggle anything into the document
+	let document = schema::Document::parse(&document.to_vec()).map_err(|errors| invalid_document(&client, "import", errors))?;
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, "application/json")], document.to_vec()).into_response());
+}
+
+// only links the startpage can open, bookmarklets and place: queries are skipped
+fn shortcut(title: &str, url: &str, icon: &str) -> Option<schema::Shortcut> {
+	let lowercase = url.trim().to_ascii_lowercase();
+	if !(lowercase.starts_with("http://") || lowercase.starts_with("https://")) || url.len() > schema::MAX_URL || !schema::is_allowed_url(url.trim()) {
+		return None;
+	}
+
+	// embedded icons are mostly too large, the icon endpoint fills in missing logos
+	let logo = match icon.len() <= schema::MAX_URL && (icon.starts_with("https://") || icon.starts_with("http://") || icon.starts_with("data:image/")) && schema::is_allowed_url(icon) {
+		true => icon.to_string(),
+		false => String::new(),
+	};
+
+	let name = match title.trim() {
+		"" => url.trim().to_string(),
+		title => title.to_string(),
+	};
+
+	return Some(schema::Shortcut { name: name.chars().take(schema::MAX_TEXT).collect(), link: url.trim().to_string(), logo: logo });
+}
+
+impl Folders {
+	// adds the link to the folder, creating the folder for its first link
+	fn push(&mut self, folder: &mut Option<usize>, name: &str, shortcut: schema::Shortcut) {
+		if self.links >= MAX_LINKS {
+			return;
+		}
+
+		let index = *folder.get_or_insert_with(|| {
+			self.folders.push(Folder { name: name.trim().chars().take(schema::MAX_TEXT).collect(), links: vec![] });
+			return self.folders.len() - 1;
+		});
+
+		self.folders[index].links.push(shortcut);
+		self.links += 1;
+	}
+}
+
+fn firefox_folder(node: &Value, folders: &mut Folders) {
+	let name = node["title"].as_str().unwrap_or_default();
+	let mut folder = None;
+
+	for child in node["children"].as_array().into_iter().flatten() {
+		match child["type"].as_str() {
+			Some("text/x-moz-place-container") => firefox_folder(child, folders),
+			Some("text/x-moz-place") => {
+				let shortcut = shortcut(child["title"].as_str().unwrap_or_default(), child["uri"].as_str().unwrap_or_default(), child["iconUri"].as_str().unwrap_or_default());
+
+				if let Some(shortcut) = shortcut {
+					folders.push(&mut folder, name, shortcut);
+				}
+			},
+			_ => (),
+		}
+	}
+}
+
+// <DT><H3>Folder</H3><DL><p><DT><A HREF="..." ICON="...">Title</A></DL><p>, tags are often not closed
+fn netscape_folders(html: &str) -> Folders {
+	let lowercase = html.to_ascii_lowercase();
+	let mut folders = Folders::default();
+	// name and folder of every open <DL>, links before the first <DL> belong to the outermost one
+	let mut stack: Vec<(String, Option<usize>)> = vec![(String::new(), None)];
+	// the <H3> in front of a <DL> names it
+	let mut heading = String::new();
+	let mut position = 0;
+
+	while let Some(start) = lowercase[position..].find("<").map(|start| start + position) {
+		let end = lowercase[start..].find(">").map(|end| end + start).unwrap_or(lowercase.len());
+		let tag = &lowercase[start + 1..end];
+		position = end;
+
+		if tag.starts_with("dl") {
+			stack.push((std::mem::take(&mut heading), None));
+		}
+		else if tag.starts_with("/dl") && stack.len() > 1 {
+			stack.pop();
+		}
+		else if tag.starts_with("h3") {
+			let text_end = lowercase[end..].find("</h3").map(|text_end| text_end + end).unwrap_or(end);
+			heading = decode_entities(html.get(end + 1..text_end).unwrap_or_default());
+			position = text_end;
+		}
+		else if tag.starts_with("a ") || tag == "a" {
+			let text_end = lowercase[end..].find("</a").map(|text_end| text_end + end).unwrap_or(end);
+			let attributes = icon::attributes(&html[start + 2..end]);
+			let attribute = |name: &str| attributes.iter().find(|(key, _)| key == name).map(|(_, value)| decode_entities(value)).unwrap_or_default();
+
+			let icon = match attribute("icon_uri") {
+				icon if icon.is_empty() => attribute("icon"),
+				icon => icon,
+			};
+
+			let shortcut = shortcut(&decode_entities(html.get(end + 1..text_end).unwrap_or_default()), &attribute("href"), &icon);
+
+			if let (Some(shortcut), Some((name, folder))) = (shortcut, stack.last_mut()) {
+				folders.push(folder, name, shortcut);
+			}
+			position = text_end;
+		}
+	}
+
+	return folders;
+}
+
+fn decode_entities(text: &str) -> String {
+	return text
+		.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&#39;", "'")
+		.replace("&#x27;", "'")
+		.replace("&amp;", "&");
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn groups(folders: Folders) -> Vec<(String, Vec<(String, String)>)> {
+		return folders.folders.into_iter()
+			.map(|folder| (folder.name, folder.links.into_iter().map(|link| (link.name, link.link)).collect()))
+			.collect();
+	}
+
+	#[test]
+	fn converts_netscape_exports() {
+		let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+	<DT><H3 ADD_DATE="1700000000" PERSONAL_TOOLBAR_FOLDER="true">Toolbar</H3>
+	<DL><p>
+		<DT><A HREF="https://www.srf.ch/" ADD_DATE="1700000000" ICON="data:image/png;base64,iVBO">SRF &amp; News</A>
+		<DT><A HREF="javascript:alert(1)">Bookmarklet</A>
+		<DT><H3>Shopping</H3>
+		<DL><p>
+			<DT><A HREF="https://www.digitec.ch/">digitec</A>
+		</DL><p>
+	</DL><p>
+	<DT><A HREF="https://example.com/?a=1&amp;b=2"></A>
+</DL>"#;
+
+		assert_eq!(groups(netscape_folders(html)), vec![
+			("Toolbar".to_string(), vec![("SRF & News".to_string(), "https://www.srf.ch/".to_string())]),
+			("Shopping".to_string(), vec![("digitec".to_string(), "https://www.digitec.ch/".to_string())]),
+			(String::new(), vec![("https://example.com/?a=1&b=2".to_string(), "https://example.com/?a=1&b=2".to_string())]),
+		]);
+	}
+
+	#[test]
+	fn converts_firefox_backups() {
+		let backup: Value = serde_json::from_str(r#"{"guid":"root________","title":"","type":"text/x-moz-place-container","children":[
+			{"title":"menu","type":"text/x-moz-place-container","children":[
+				{"title":"Mozilla","type":"text/x-moz-place","uri":"https://www.mozilla.org/","iconUri":"https://www.mozilla.org/favicon.ico"},
+				{"type":"text/x-moz-place-separator"},
+				{"title":"Recent","type":"text/x-moz-place","uri":"place:sort=8"}
+			]},
+			{"title":"toolbar","type":"text/x-moz-place-container","children":[
+				{"title":"News","type":"text/x-moz-place-container","children":[{"title":"SRF","type":"text/x-moz-place","uri":"https://www.srf.ch/"}]}
+			]}
+		]}"#).unwrap();
+
+		let mut folders = Folders::default();
+		firefox_folder(&backup, &mut folders);
+
+		assert_eq!(folders.folders[0].links[0].logo, "https://www.mozilla.org/favicon.ico");
+		assert_eq!(groups(folders), vec![
+			("menu".to_string(), vec![("Mozilla".to_string(), "https://www.mozilla.org/".to_string())]),
+			("News".to_string(), vec![("SRF".to_string(), "https://www.srf.ch/".to_string())]),
+		]);
+	}
+
+	#[test]
+	fn drops_unsafe_links_and_logos() {
+		let html = r#"<DL><p>
+	<DT><A HREF="https://example.com/" ICON="https://example.com/i.png&quot; onerror=&quot;alert(1)">Example &quot;&gt;&lt;img&gt;</A>
+	<DT><A HREF="https://example.com/&quot;&gt;">Quoted</A>
+	<DT><A HREF="https://example.com/a&#x27;b">Apostrophe</A>
+</DL>"#;
+
+		let folders = netscape_folders(html);
+		let links = &folders.folders[0].links;
+
+		assert_eq!(links.len(), 1);
+		assert_eq!(links[0].name, "Example \"><img>");
+		assert_eq!(links[0].logo, "");
+	}
+
+	#[test]
+	fn stops_at_max_links() {
+		let html: String = (0..MAX_LINKS + 10).map(|index| format!("<DL><DT><A HREF=\"https://example.com/{index}\">{index}</A></DL>")).collect();
+
+		let folders = netscape_folders(&html);
+		assert_eq!(folders.links, MAX_LINKS);
+		assert_eq!(folders.folders.len(), MAX_LINKS);
+		assert_eq!(folders.folders.iter().map(|folder| folder.links.len()).sum::<usize>(), MAX_LINKS);
+	}
+}
@@ -0,0 +1,441 @@
+use axum::{
+	extract::{Path, State},
+	http::{HeaderMap, StatusCode, header::{CONTENT_TYPE, LOCATION}},
+	response::{IntoResponse, Response}
+};
+use futures_util::{stream, StreamExt};
+use reqwest::{Method, Url};
+use serde::Serialize;
+use serde_json::{self, Value};
+use std::{
+	collections::{HashMap, HashSet},
+	env::var,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+use tokio::fs;
+
+use crate::{
+	client_ip::ClientIp,
+	error,
+	http_client::{self, HttpClient, Upstream}
+};
+use super::{etag, invalid_document, schema, Startpage};
+
+const REPORT_DIR: &str = ".links";
+// links checked at the same time per run
+const MAX_PARALLEL: usize = 8;
+
+// checks the links of stored documents in the background, one run per profile at a time
+pub struct LinkChecker {
+	upstream: Upstream,
+	dir: PathBuf,
+	timeout: Duration,
+	// links answering slower are reported as slow
+	slow: Duration,
+	cache_duration: Duration,
+	cache: Mutex<HashMap<String, (Instant, Check)>>,
+	running: Mutex<HashSet<String>>,
+}
+
+// marks a run of a profile, removed when the run ends or panics
+struct Running {
+	links: Arc<LinkChecker>,
+	profile: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum LinkStatus {
+	Ok,
+	Broken,
+	Redirected,
+	Skipped,
+}
+
+#[derive(Clone, Serialize)]
+struct Check {
+	status: LinkStatus,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	http_status: Option<u16>,
+	// final url of redirected links
+	#[serde(skip_serializing_if = "Option::is_none")]
+	location: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<String>,
+	duration_ms: u64,
+	slow: bool,
+}
+
+#[derive(Serialize)]
+struct LinkReport {
+	// json pointer to the link in the document
+	path: String,
+	name: String,
+	url: String,
+	#[serde(flatten)]
+	check: Check,
+}
+
+#[derive(Default, Serialize)]
+struct Summary {
+	total: usize,
+	ok: usize,
+	broken: usize,
+	redirected: usize,
+	slow: usize,
+	skipped: usize,
+}
+
+// only links which need attention are listed, the summary counts all of them
+#[derive(Serialize)]
+struct Report {
+	checked_at: u64,
+	// version of the document which was checked
+	etag: String,
+	summary: Summary,
+	links: Vec<LinkReport>,
+}
+
+impl LinkChecker {
+	pub fn from_env(http_client: &HttpClient, dir: PathBuf) -> LinkChecker {
+		return LinkChecker {
+			upstream: http_client.public_upstream("startpage_links"),
+			dir: dir.join(REPORT_DIR),
+			timeout: Duration::from_secs(number("STARTPAGE_LINK_TIMEOUT", 10).max(1)),
+			slow: Duration::from_millis(number("STARTPAGE_LINK_SLOW_MS", 3000)),
+			cache_duration: Duration::from_secs(number("STARTPAGE_LINK_CACHE", 3600)),
+			cache: Mutex::new(HashMap::new()),
+			running: Mutex::new(HashSet::new()),
+		};
+	}
+
+	fn report_path(&self, profile: &str) -> PathBuf {
+		return self.dir.join(format!("{profile}.json"));
+	}
+
+	fn is_running(&self, profile: &str) -> bool {
+		return self.running.lock().unwrap().contains(profile);
+	}
+
+	// None while a run of the profile is going on
+	fn start(links: &Arc<LinkChecker>, profile: &str) -> Option<Running> {
+		if !links.running.lock().unwrap().insert(profile.to_string()) {
+			return None;
+		}
+
+		return Some(Running { links: links.clone(), profile: profile.to_string() });
+	}
+
+	async fn run(&self, profile: &str, etag: String, document: schema::Document) {
+		let mut links = vec![];
+
+		for (element_index, element) in document.elements.iter().enumerate() {
+			let schema::Element::Shortcut(container) = element;
+
+			for (index, shortcut) in container.content.iter().enumerate() {
+				links.push((format!("/elements/{element_index}/content/{index}/link"), shortcut.name.clone(), shortcut.link.clone()));
+			}
+		}
+
+		// the same site is often linked more than once
+		let urls: HashSet<String> = links.iter().map(|(_, _, url)| url.clone()).collect();
+		let checks: HashMap<String, Check> = stream::iter(urls)
+			.map(|url| async move {
+				let check = self.cached_check(&url).await;
+				return (url, check);
+			})
+			.buffer_unordered(MAX_PARALLEL)
+			.collect().await;
+
+		let mut summary = Summary { total: links.len(), ..Summary::default() };
+		let mut reports = vec![];
+
+		for (path, name, url) in links {
+			let Some(check) = checks.get(&url).cloned() else {
+				continue;
+			};
+
+			match check.status {
+				LinkStatus::Ok => summary.ok += 1,
+				LinkStatus::Broken => summary.broken += 1,
+				LinkStatus::Redirected => summary.redirected += 1,
+				LinkStatus::Skipped => summary.skipped += 1,
+			}
+			if check.slow {
+				summary.slow += 1;
+			}
+
+			if check.status != LinkStatus::Ok || check.slow {
+				reports.push(LinkReport { path, name, url, check });
+			}
+		}
+
+		println!("[Startpage-Links] Checked {} links of {profile}, {} broken, {} redirected, {} slow", summary.total, summary.broken, summary.redirected, summary.slow);
+
+		let report = Report { checked_at: now(), etag: etag, summary: summary, links: reports };
+		let path = self.report_path(profile);
+		let part = path.with_extension("json.part");
+
+		let write = async {
+			let content = serde_json::to_vec(&report)?;
+			fs::create_dir_all(&self.dir).await?;
+			fs::write(&part, content).await?;
+			return fs::rename(&part, &path).await;
+		};
+
+		if let Err(e) = write.await {
+			eprintln!("[Startpage-Links] Could not write report of {profile}: {e}");
+		}
+	}
+
+	async fn cached_check(&self, url: &str) -> Check {
+		if let Some((checked, check)) = self.cache.lock().unwrap().get(url) && checked.elapsed() < self.cache_duration {
+			return check.clone();
+		}
+
+		let check = self.check(url).await;
+
+		let mut cache = self.cache.lock().unwrap();
+		cache.retain(|_, (checked, _)| checked.elapsed() < self.cache_duration);
+		cache.insert(url.to_string(), (Instant::now(), check.clone()));
+
+		return check;
+	}
+
+	// HEAD first, many servers only answer GET correctly so failures are retried with it
+	async fn check(&self, url: &str) -> Check {
+		let skipped = |reason: &str| Check { status: LinkStatus::Skipped, http_status: None, location: None, error: Some(reason.to_string()), duration_ms: 0, slow: false };
+
+		let Ok(parsed) = Url::parse(url) else {
+			return skipped("not an absolute url");
+		};
+
+		if !http_client::is_public_url(&parsed) {
+			return skipped("only public http(s) links are checked");
+		}
+
+		let mut start = Instant::now();
+		let mut result = self.upstream.request(Method::HEAD, parsed.clone()).timeout(self.timeout).send().await;
+
+		// only the request which answered counts, a rejected HEAD doesn't make the link slow
+		if result.as_ref().map_or(true, |response| !response.status().is_success()) {
+			start = Instant::now();
+			result = self.upstream.get(parsed.clone()).timeout(self.timeout).send().await;
+		}
+
+		let duration = start.elapsed();
+		let mut check = Check { status: LinkStatus::Ok, http_status: None, location: None, error: None, duration_ms: duration.as_millis() as u64, slow: duration > self.slow };
+
+		match result {
+			Ok(response) => {
+				check.http_status = Some(response.status().as_u16());
+
+				if !response.status().is_success() {
+					check.status = LinkStatus::Broken;
+				}
+				else if *response.url() != parsed {
+					check.status = LinkStatus::Redirected;
+					check.location = Some(response.url().to_string());
+				}
+			},
+			Err(e) => {
+				check.status = LinkStatus::Broken;
+				check.error = Some(match e.is_timeout() {
+					true => format!("no answer within {}s", self.timeout.as_secs()),
+					false => error_chain(&e),
+				});
+			},
+		}
+
+		return check;
+	}
+}
+
+// starts a run for the current document, the report is fetched with GET afterwards
+pub async fn trigger(State(startpage): State<Startpage>, client: ClientIp, Path(profile): Path<String>, headers: HeaderMap) -> Result<Response, Response> {
+	startpage.authorize(&profile, &client, &headers)?;
+
+	let accepted = (StatusCode::ACCEPTED, [(LOCATION, format!("/api/startpage/{profile}/links"))]);
+
+	let Some(running) = LinkChecker::start(&startpage.links, &profile) else {
+		return Ok(accepted.into_response());
+	};
+
+	let document = match startpage.read(&profile).await {
+		Ok(Some(content)) => schema::Document::parse(&content).map(|document| (etag(&content), document)).map_err(|errors| invalid_document(&client, &profile, errors)),
+		Ok(None) => Err((StatusCode::NOT_FOUND, format!("{profile} has no startpage yet")).into_response()),
+		Err(response) => Err(response),
+	};
+
+	let (etag, document) = document?;
+
+	println!("[Startpage-Links] {client} started a link check of {profile}");
+
+	tokio::spawn(async move {
+		running.links.run(&running.profile, etag, document).await;
+	});
+
+	return Ok(accepted.into_response());
+}
+
+pub async fn report(State(startpage): State<Startpage>, client: ClientIp, Path(profile): Path<String>, headers: HeaderMap) -> Result<Response, Response> {
+	startpage.authorize(&profile, &client, &headers)?;
+
+	let running = startpage.links.is_running(&profile);
+
+	let mut report: Value = match fs::read(startpage.links.report_path(&profile)).await {
+		Ok(content) => serde_json::from_slice(&content).map_err(|e| error::map_serde_error(e, "Startpage-Links"))?,
+		Err(_) if running => return Ok((StatusCode::ACCEPTED, [(CONTENT_TYPE, "application/json")], r#"{"running":true}"#).into_response()),
+		Err(_) => return Ok((StatusCode::NOT_FOUND, format!("The links of {profile} were not checked yet")).into_response()),
+	};
+
+	// a report of an older version is still useful, the client can tell by the etag
+	if let Some(report) = report.as_object_mut() {
+		report.insert("running".to_string(), Value::from(running));
+	}
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, "application/json")], report.to_string()).into_response());
+}
+
+impl Drop for Running {
+	fn drop(&mut self) {
+		self.links.running.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.profile);
+	}
+}
+
+// the cause, like a blocked address or a dns failure, is only part of the source chain
+fn error_chain(e: &reqwest::Error) -> String {
+	let mut message = e.to_string();
+	let mut source = std::error::Error::source(e);
+
+	while let Some(cause) = source {
+		message = format!("{message}: {cause}");
+		source = cause.source();
+	}
+
+	return message;
+}
+
+fn now() -> u64 {
+	return SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+}
+
+fn number(name: &str, default: u64) -> u64 {
+	return var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{Router, http::Method as HttpMethod, routing::any};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	const SLOW: Duration = Duration::from_millis(300);
+
+	// HEAD and GET answers of the mock site, per path
+	async fn site(requests: Arc<AtomicUsize>) -> String {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+
+		let site = Router::new()
+			.route("/ok", any(|| async { StatusCode::OK }))
+			.route("/missing", any(|| async { StatusCode::NOT_FOUND }))
+			.route("/moved", any(|| async { (StatusCode::MOVED_PERMANENTLY, [(LOCATION, "/ok")]) }))
+			.route("/head-rejected", any(|method: HttpMethod| async move {
+				if method == HttpMethod::HEAD {
+					tokio::time::sleep(SLOW).await;
+					return StatusCode::METHOD_NOT_ALLOWED;
+				}
+				return StatusCode::OK;
+			}))
+			.route("/slow", any(|| async {
+				tokio::time::sleep(SLOW).await;
+				return StatusCode::OK;
+			}))
+			.route("/counted", any(move || async move {
+				requests.fetch_add(1, Ordering::SeqCst);
+				return StatusCode::OK;
+			}));
+
+		tokio::spawn(async move { axum::serve(listener, site).await.unwrap() });
+
+		// a name, literal loopback addresses are skipped as not public
+		return format!("http://localhost:{port}");
+	}
+
+	fn checker() -> LinkChecker {
+		return LinkChecker {
+			upstream: http_client::Upstream::unrestricted(),
+			dir: PathBuf::new(),
+			timeout: Duration::from_secs(5),
+			slow: SLOW / 2,
+			cache_duration: Duration::from_secs(3600),
+			cache: Mutex::new(HashMap::new()),
+			running: Mutex::new(HashSet::new()),
+		};
+	}
+
+	#[tokio::test]
+	async fn classifies_links() {
+		let site = site(Arc::new(AtomicUsize::new(0))).await;
+		let links = checker();
+
+		let ok = links.check(&format!("{site}/ok")).await;
+		assert_eq!((ok.status, ok.http_status, ok.slow), (LinkStatus::Ok, Some(200), false));
+
+		let missing = links.check(&format!("{site}/missing")).await;
+		assert_eq!((missing.status, missing.http_status), (LinkStatus::Broken, Some(404)));
+
+		let moved = links.check(&format!("{site}/moved")).await;
+		assert_eq!((moved.status, moved.location), (LinkStatus::Redirected, Some(format!("{site}/ok"))));
+
+		let slow = links.check(&format!("{site}/slow")).await;
+		assert_eq!((slow.status, slow.slow), (LinkStatus::Ok, true));
+
+		assert_eq!(links.check("http://127.0.0.1/").await.status, LinkStatus::Skipped);
+	}
+
+	#[tokio::test]
+	async fn falls_back_to_get_and_times_only_the_answer() {
+		let site = site(Arc::new(AtomicUsize::new(0))).await;
+
+		let check = checker().check(&format!("{site}/head-rejected")).await;
+		assert_eq!((check.status, check.http_status), (LinkStatus::Ok, Some(200)));
+		assert!(!check.slow, "the rejected HEAD took {}ms", check.duration_ms);
+	}
+
+	#[tokio::test]
+	async fn caches_checks() {
+		let requests = Arc::new(AtomicUsize::new(0));
+		let site = site(requests.clone()).await;
+		let links = checker();
+
+		links.cached_check(&format!("{site}/counted")).await;
+		links.cached_check(&format!("{site}/counted")).await;
+		assert_eq!(requests.load(Ordering::SeqCst), 1);
+	}
+
+	#[tokio::test]
+	async fn guards_running_checks() {
+		let links = Arc::new(checker());
+
+		let running = LinkChecker::start(&links, "home");
+		assert!(running.is_some());
+		assert!(LinkChecker::start(&links, "home").is_none());
+		assert!(LinkChecker::start(&links, "work").is_some());
+
+		drop(running);
+		assert!(!links.is_running("home"));
+
+		// a panicking run must not block the profile forever
+		let running = LinkChecker::start(&links, "home").unwrap();
+		let run = tokio::spawn(async move {
+			let _running = running;
+			panic!("run failed");
+		});
+		assert!(run.await.is_err());
+		assert!(!links.is_running("home"));
+	}
+}
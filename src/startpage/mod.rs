@@ -0,0 +1,374 @@
+use axum::{
+	extract::{DefaultBodyLimit, Path, State},
+	http::{HeaderMap, HeaderValue, StatusCode, header::{AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH}},
+	response::{IntoResponse, Response},
+	routing::{get, post},
+	Router
+};
+use serde_json;
+use sha2::{Digest, Sha256};
+use std::{
+	collections::HashMap,
+	path::PathBuf,
+	sync::Arc
+};
+use subtle::ConstantTimeEq;
+use tokio::{fs, sync::Mutex};
+
+use crate::{
+	client_ip::ClientIp,
+	config,
+	error,
+	http_client::HttpClient
+};
+
+mod history;
+mod icon;
+mod import;
+mod links;
+mod schema;
+
+// names used by routes which don't belong to a profile
+const RESERVED_PROFILES: [&str; 2] = ["icon", "import"];
+
+#[derive(Clone)]
+struct Startpage {
+	dir: PathBuf,
+	// access token of every profile, profiles without a token don't exist
+	tokens: Arc<HashMap<String, String>>,
+	// read, compare and write of a document must not interleave
+	write_lock: Arc<Mutex<()>>,
+	history: Arc<history::History>,
+	icons: Arc<icon::Icons>,
+	links: Arc<links::LinkChecker>,
+}
+
+pub fn router(http_client: &HttpClient) -> Option<Router> {
+	let mut env = config::RequiredEnv::new("Startpage");
+
+	let dir = env.get("STARTPAGE_DIR");
+	let tokens = env.get("STARTPAGE_TOKENS");
+
+	if !env.is_complete() {
+		return None;
+	}
+
+	let tokens: HashMap<String, String> = config::parse_map(&tokens).into_iter()
+		.filter(|(profile, token)| match valid_profile(profile) && !token.is_empty() {
+			true => true,
+			false => {
+				eprintln!("[Startpage] Ignoring profile {profile:?}, invalid name or empty token");
+				false
+			}
+		})
+		.collect();
+
+	let state = Startpage {
+		history: Arc::new(history::History::from_env(PathBuf::from(&dir))),
+		icons: Arc::new(icon::Icons::from_env(http_client, PathBuf::from(&dir))),
+		links: Arc::new(links::LinkChecker::from_env(http_client, PathBuf::from(&dir))),
+		dir: PathBuf::from(dir),
+		tokens: Arc::new(tokens),
+		write_lock: Arc::new(Mutex::new(())),
+	};
+
+	return Some(routes(state));
+}
+
+fn routes(state: Startpage) -> Router {
+	return Router::new()
+		.route("/icon", get(icon::icon))
+		.route("/import", post(import::import).layer(DefaultBodyLimit::max(import::MAX_IMPORT_SIZE)))
+		.route("/{profile}", get(load).put(store))
+		.route("/{profile}/revisions", get(history::list))
+		.route("/{profile}/revisions/{revision}", get(history::revision))
+		.route("/{profile}/revisions/{revision}/restore", post(history::restore))
+		.route("/{profile}/diff", get(history::diff))
+		.route("/{profile}/links", get(links::report).post(links::trigger))
+		.with_state(state);
+}
+
+impl Startpage {
+	// the bearer has to be the token of the requested profile, unknown profiles look like a wrong token so names can't be probed
+	fn authorize(&self, profile: &str, client: &ClientIp, headers: &HeaderMap) -> Result<(), Response> {
+		let bearer = headers
+			.get(AUTHORIZATION)
+			.and_then(|value| value.as_bytes().strip_prefix(b"Bearer "))
+			.ok_or_else(|| error::generic_unauthorized_error(&format!("[Startpage] {client} Bearer required for {profile}")))?;
+
+		let valid = self.tokens.get(profile).is_some_and(|token| bool::from(bearer.ct_eq(token.as_bytes())));
+
+		if !valid {
+			return Err(error::generic_unauthorized_error(&format!("[Startpage] {client} Bearer invalid for {profile}")));
+		}
+
+		return Ok(());
+	}
+
+	fn document_path(&self, profile: &str) -> PathBuf {
+		return self.dir.join(format!("{profile}.json"));
+	}
+
+	async fn read(&self, profile: &str) -> Result<Option<Vec<u8>>, Response> {
+		match fs::read(self.document_path(profile)).await {
+			Ok(content) => return Ok(Some(content)),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(error::generic_internal_error(&format!("[Startpage] Could not read {profile}: {e}"))),
+		}
+	}
+
+	async fn write(&self, profile: &str, content: &[u8]) -> Result<(), Response> {
+		let path = self.document_path(profile);
+		let part = path.with_extension("json.part");
+
+		let write = async {
+			fs::create_dir_all(&self.dir).await?;
+			fs::write(&part, content).await?;
+			return fs::rename(&part, &path).await;
+		};
+
+		return write.await.map_err(|e| error::generic_internal_error(&format!("[Startpage] Could not write {profile}: {e}")));
+	}
+
+	// write a new version after checking the preconditions, shared by uploads and restores
+	async fn replace(&self, profile: &str, client: &ClientIp, headers: &HeaderMap, content: &[u8]) -> Result<Response, Response> {
+		let if_match = headers.get(IF_MATCH).and_then(|value| value.to_str().ok());
+		let create_only = headers.get(IF_NONE_MATCH).and_then(|value| value.to_str().ok()).is_some_and(|value| value.trim() == "*");
+
+		if if_match.is_none() && !create_only {
+			return Err((StatusCode::PRECONDITION_REQUIRED, "If-Match with the current ETag or If-None-Match: * is required").into_response());
+		}
+
+		let _lock = self.write_lock.lock().await;
+		let current = self.read(profile).await?;
+
+		let precondition = match (&current, if_match) {
+			(Some(_), _) if create_only => false,
+			(Some(content), Some(if_match)) => matches_etag(if_match, &etag(content)),
+			(None, _) => create_only,
+			(Some(_), None) => false,
+		};
+
+		if !precondition {
+			let current_etag = current.as_deref().map(etag).unwrap_or_default();
+			eprintln!("[Startpage] {client} tried to overwrite a changed version of {profile}");
+			return Ok((StatusCode::PRECONDITION_FAILED, [(ETAG, header_value(&current_etag))], "The startpage was changed in the meantime").into_response());
+		}
+
+		self.write(profile, content).await?;
+		self.history.record(profile, current.as_deref(), content).await;
+
+		println!("[Startpage] {client} stored {profile}");
+
+		let status = if current.is_some() { StatusCode::NO_CONTENT } else { StatusCode::CREATED };
+		return Ok((status, [(ETAG, header_value(&etag(content)))]).into_response());
+	}
+
+	// documents of older versions are migrated once when they are loaded
+	async fn upgrade(&self, profile: &str) -> Result<Option<Vec<u8>>, Response> {
+		let _lock = self.write_lock.lock().await;
+
+		let Some(current) = self.read(profile).await? else {
+			return Ok(None);
+		};
+
+		if !schema::is_outdated(&current) {
+			return Ok(Some(current));
+		}
+
+		let content = match schema::Document::parse(&current) {
+			Ok(document) => document.to_vec(),
+			Err(errors) => {
+				eprintln!("[Startpage] Could not migrate {profile}, {} errors starting at {:?}", errors.len(), errors[0].path);
+				return Ok(Some(current));
+			}
+		};
+
+		self.write(profile, &content).await?;
+		self.history.record(profile, Some(&current), &content).await;
+
+		println!("[Startpage] Migrated {profile} to version {}", schema::CURRENT_VERSION);
+
+		return Ok(Some(content));
+	}
+}
+
+async fn load(State(startpage): State<Startpage>, client: ClientIp, Path(profile): Path<String>, headers: HeaderMap) -> Result<Response, Response> {
+	startpage.authorize(&profile, &client, &headers)?;
+
+	let mut content = startpage.read(&profile).await?;
+
+	if content.as_deref().is_some_and(schema::is_outdated) {
+		content = startpage.upgrade(&profile).await?;
+	}
+
+	let Some(content) = content else {
+		return Ok((StatusCode::NOT_FOUND, format!("{profile} has no startpage yet")).into_response());
+	};
+
+	let etag = etag(&content);
+
+	if headers.get(IF_NONE_MATCH).and_then(|value| value.to_str().ok()).is_some_and(|value| matches_etag(value, &etag)) {
+		return Ok((StatusCode::NOT_MODIFIED, [(ETAG, header_value(&etag))]).into_response());
+	}
+
+	println!("[Startpage] {client} loaded {profile}");
+
+	return Ok((StatusCode::OK, [(CONTENT_TYPE, HeaderValue::from_static("application/json")), (ETAG, header_value(&etag))], content).into_response());
+}
+
+// documents are only replaced when the client saw the current version, new documents need If-None-Match: *
+async fn store(State(startpage): State<Startpage>, client: ClientIp, Path(profile): Path<String>, headers: HeaderMap, body: String) -> Result<Response, Response> {
+	startpage.authorize(&profile, &client, &headers)?;
+
+	let document = schema::Document::parse(body.as_bytes()).map_err(|errors| invalid_document(&client, &profile, errors))?;
+
+	return startpage.replace(&profile, &client, &headers, &document.to_vec()).await;
+}
+
+// 422 with the json pointer and reason of every problem
+fn invalid_document(client: &ClientIp, profile: &str, errors: Vec<schema::SchemaError>) -> Response {
+	eprintln!("[Startpage] {client} sent an invalid document for {profile}, {} errors", errors.len());
+
+	let body = serde_json::json!({ "errors": errors }).to_string();
+	return (StatusCode::UNPROCESSABLE_ENTITY, [(CONTENT_TYPE, "application/json")], body).into_response();
+}
+
+// strong etag of the stored bytes
+fn etag(content: &[u8]) -> String {
+	return format!("\"{}\"", hex::encode(Sha256::digest(content)));
+}
+
+// If-Match and If-None-Match can contain a list of etags or *
+fn matches_etag(header: &str, etag: &str) -> bool {
+	return header.split(",").map(|value| value.trim()).any(|value| value == "*" || value == etag);
+}
+
+fn header_value(etag: &str) -> HeaderValue {
+	return HeaderValue::from_str(etag).unwrap_or(HeaderValue::from_static("\"\""));
+}
+
+// profile names end up in file names
+fn valid_profile(profile: &str) -> bool {
+	return !RESERVED_PROFILES.contains(&profile) && !profile.is_empty() && profile.len() <= 64 && profile.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::{body::{self, Body}, http::{HeaderName, Request}};
+	use std::path::Path as FsPath;
+	use tower::ServiceExt;
+
+	pub(super) const PROFILE: &str = "home";
+	const TOKEN: &str = "profile-token";
+	pub(super) const DOCUMENT: &str = r##"{"version":1,"style":{"backgroundColor":"#131319","foregroundColor":"#2d2d38"},"elements":[{"type":"Shortcut","styles":{"width":"100px","cols":4,"backgroundColor":"#2d2d38"},"content":[{"name":"SRF","link":"https://www.srf.ch","logo":""}]}]}"##;
+	pub(super) const CHANGED_DOCUMENT: &str = r##"{"version":1,"style":{"backgroundColor":"#000000","foregroundColor":"#2d2d38"},"elements":[]}"##;
+
+	// one profile storing its documents below dir
+	pub(super) fn state(dir: &FsPath) -> Startpage {
+		let http_client = HttpClient::from_env();
+
+		return Startpage {
+			history: Arc::new(history::History::from_env(dir.to_path_buf())),
+			icons: Arc::new(icon::Icons::from_env(&http_client, dir.to_path_buf())),
+			links: Arc::new(links::LinkChecker::from_env(&http_client, dir.to_path_buf())),
+			dir: dir.to_path_buf(),
+			tokens: Arc::new(HashMap::from([(PROFILE.to_string(), TOKEN.to_string())])),
+			write_lock: Arc::new(Mutex::new(())),
+		};
+	}
+
+	// request of the profile with its bearer
+	pub(super) async fn send(state: &Startpage, method: &str, uri: &str, headers: &[(HeaderName, &str)], body: &str) -> (StatusCode, HeaderMap, String) {
+		let mut request = Request::builder().method(method).uri(format!("/{PROFILE}{uri}")).header(AUTHORIZATION, format!("Bearer {TOKEN}"));
+		for (name, value) in headers {
+			request = request.header(name, *value);
+		}
+
+		let response = routes(state.clone()).oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+		let status = response.status();
+		let headers = response.headers().clone();
+		let content = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+		return (status, headers, String::from_utf8_lossy(&content).to_string());
+	}
+
+	pub(super) fn etag_of(headers: &HeaderMap) -> String {
+		return headers.get(ETAG).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+	}
+
+	#[tokio::test]
+	async fn hides_which_profiles_exist() {
+		let dir = crate::temp_dir::TempDir::new("startpage");
+		let state = state(dir.path());
+
+		let request = |uri: &str, bearer: &str| Request::builder().uri(uri).header(AUTHORIZATION, format!("Bearer {bearer}")).body(Body::empty()).unwrap();
+		let unknown = routes(state.clone()).oneshot(request("/unknown", TOKEN)).await.unwrap();
+		let wrong_token = routes(state.clone()).oneshot(request(&format!("/{PROFILE}"), "wrong")).await.unwrap();
+
+		assert_eq!(unknown.status(), StatusCode::UNAUTHORIZED);
+		assert_eq!(wrong_token.status(), StatusCode::UNAUTHORIZED);
+		// the same answer apart from the requested name
+		let unknown = body::to_bytes(unknown.into_body(), usize::MAX).await.unwrap();
+		let wrong_token = body::to_bytes(wrong_token.into_body(), usize::MAX).await.unwrap();
+		assert_eq!(String::from_utf8_lossy(&unknown).replace("unknown", PROFILE), String::from_utf8_lossy(&wrong_token));
+	}
+
+	#[tokio::test]
+	async fn requires_a_precondition_to_store() {
+		let dir = crate::temp_dir::TempDir::new("startpage");
+		let state = state(dir.path());
+
+		assert_eq!(send(&state, "PUT", "", &[], DOCUMENT).await.0, StatusCode::PRECONDITION_REQUIRED);
+		assert!(!state.document_path(PROFILE).exists());
+
+		let (status, headers, _) = send(&state, "PUT", "", &[(IF_NONE_MATCH, "*")], DOCUMENT).await;
+		assert_eq!(status, StatusCode::CREATED);
+		assert!(!etag_of(&headers).is_empty());
+
+		// a second client creating the same profile must not overwrite the first one
+		assert_eq!(send(&state, "PUT", "", &[(IF_NONE_MATCH, "*")], CHANGED_DOCUMENT).await.0, StatusCode::PRECONDITION_FAILED);
+		assert_eq!(send(&state, "PUT", "", &[], CHANGED_DOCUMENT).await.0, StatusCode::PRECONDITION_REQUIRED);
+	}
+
+	#[tokio::test]
+	async fn rejects_stale_if_match() {
+		let dir = crate::temp_dir::TempDir::new("startpage");
+		let state = state(dir.path());
+
+		let (_, headers, _) = send(&state, "PUT", "", &[(IF_NONE_MATCH, "*")], DOCUMENT).await;
+		let first = etag_of(&headers);
+
+		let (status, headers, _) = send(&state, "PUT", "", &[(IF_MATCH, &first)], CHANGED_DOCUMENT).await;
+		assert_eq!(status, StatusCode::NO_CONTENT);
+		let second = etag_of(&headers);
+		assert_ne!(first, second);
+
+		let (status, headers, _) = send(&state, "PUT", "", &[(IF_MATCH, &first)], DOCUMENT).await;
+		assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+		assert_eq!(etag_of(&headers), second);
+
+		let (_, _, content) = send(&state, "GET", "", &[], "").await;
+		assert_eq!(etag(content.as_bytes()), second);
+	}
+
+	#[tokio::test]
+	async fn answers_not_modified_for_a_matching_etag() {
+		let dir = crate::temp_dir::TempDir::new("startpage");
+		let state = state(dir.path());
+
+		let (_, headers, _) = send(&state, "PUT", "", &[(IF_NONE_MATCH, "*")], DOCUMENT).await;
+		let current = etag_of(&headers);
+
+		let (status, headers, content) = send(&state, "GET", "", &[(IF_NONE_MATCH, &format!("\"other\", {current}"))], "").await;
+		assert_eq!(status, StatusCode::NOT_MODIFIED);
+		assert_eq!(etag_of(&headers), current);
+		assert!(content.is_empty());
+
+		let (status, headers, content) = send(&state, "GET", "", &[(IF_NONE_MATCH, "\"other\"")], "").await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(etag_of(&headers), current);
+		assert_eq!(etag(content.as_bytes()), current);
+	}
+}
@@ -0,0 +1,396 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{self, Map, Value};
+
+// documents without a version were written by the first frontend, before the backend knew them
+pub const CURRENT_VERSION: u64 = 1;
+
+// one entry per version, MIGRATIONS[n] turns version n into n + 1
+const MIGRATIONS: [fn(&mut Map<String, Value>); CURRENT_VERSION as usize] = [
+	migrate_v0,
+];
+
+const MAX_COLS: u64 = 24;
+pub const MAX_TEXT: usize = 200;
+pub const MAX_URL: usize = 2048;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+	pub version: u64,
+	pub style: Style,
+	pub elements: Vec<Element>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Style {
+	pub background_color: String,
+	pub foreground_color: String,
+}
+
+// the type is the name of the frontend class which renders the container
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Element {
+	Shortcut(ShortcutContainer),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ShortcutContainer {
+	// title shown above the group, imported from the bookmark folder
+	#[serde(default, skip_serializing_if = "String::is_empty")]
+	pub name: String,
+	pub styles: ShortcutStyles,
+	pub content: Vec<Shortcut>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortcutStyles {
+	// css length of one column
+	pub width: String,
+	pub cols: u64,
+	pub background_color: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Shortcut {
+	pub name: String,
+	pub link: String,
+	pub logo: String,
+}
+
+// same defaults as a new document and a new group in the frontend
+impl Default for Style {
+	fn default() -> Style {
+		return Style { background_color: "#131319".to_string(), foreground_color: "#2d2d38".to_string() };
+	}
+}
+
+impl Default for ShortcutStyles {
+	fn default() -> ShortcutStyles {
+		return ShortcutStyles { width: "100px".to_string(), cols: 4, background_color: "#2d2d38".to_string() };
+	}
+}
+
+#[derive(Serialize)]
+pub struct SchemaError {
+	// json pointer to the invalid value
+	pub path: String,
+	pub message: String,
+}
+
+// checks a document and collects every problem instead of stopping at the first one
+struct Validator {
+	errors: Vec<SchemaError>,
+}
+
+impl Document {
+	// migrate and validate an uploaded document, stored in its normalized form
+	pub fn parse(content: &[u8]) -> Result<Document, Vec<SchemaError>> {
+		let value: Value = serde_json::from_slice(content).map_err(|e| vec![SchemaError { path: String::new(), message: e.to_string() }])?;
+
+		let mut validator = Validator { errors: vec![] };
+		let document = migrate(value).map_err(|error| vec![error]).map(|value| validator.document(&value))?;
+
+		match document {
+			Some(document) if validator.errors.is_empty() => return Ok(document),
+			_ => return Err(validator.errors),
+		}
+	}
+
+	pub fn to_vec(&self) -> Vec<u8> {
+		return serde_json::to_vec(self).unwrap_or_default();
+	}
+}
+
+// older documents are stored as they are until they are uploaded again
+pub fn is_outdated(content: &[u8]) -> bool {
+	return serde_json::from_slice::<Value>(content).is_ok_and(|value| version(&value).is_some_and(|version| version < CURRENT_VERSION));
+}
+
+// the checks of links and logos in uploads, for documents built by the backend
+pub fn is_allowed_url(url: &str) -> bool {
+	let mut validator = Validator { errors: vec![] };
+	return validator.url(&Value::from(url), "").is_some();
+}
+
+fn version(value: &Value) -> Option<u64> {
+	let document = value.as_object()?;
+
+	return match document.get("version") {
+		None => Some(0),
+		Some(version) => version.as_u64(),
+	};
+}
+
+fn migrate(mut value: Value) -> Result<Value, SchemaError> {
+	let Some(mut version) = version(&value) else {
+		return Err(SchemaError { path: "/version".to_string(), message: "expected a document with a numeric version".to_string() });
+	};
+
+	if version > CURRENT_VERSION {
+		return Err(SchemaError { path: "/version".to_string(), message: format!("version {version} is newer than the supported version {CURRENT_VERSION}") });
+	}
+
+	if let Some(document) = value.as_object_mut() {
+		while version < CURRENT_VERSION {
+			MIGRATIONS[version as usize](document);
+			version += 1;
+		}
+
+		document.insert("version".to_string(), Value::from(CURRENT_VERSION));
+	}
+
+	return Ok(value);
+}
+
+// the settings dialog stored the column count as the string of the input field
+fn migrate_v0(document: &mut Map<String, Value>) {
+	let elements = document.get_mut("elements").and_then(|elements| elements.as_array_mut()).into_iter().flatten();
+
+	for styles in elements.filter_map(|element| element.get_mut("styles")).filter_map(|styles| styles.as_object_mut()) {
+		if let Some(cols) = styles.get("cols").and_then(|cols| cols.as_str()).and_then(|cols| cols.trim().parse::<u64>().ok()) {
+			styles.insert("cols".to_string(), Value::from(cols));
+		}
+	}
+}
+
+impl Validator {
+	fn error(&mut self, path: &str, message: impl Into<String>) {
+		self.errors.push(SchemaError { path: path.to_string(), message: message.into() });
+	}
+
+	fn document(&mut self, value: &Value) -> Option<Document> {
+		let document = self.object(value, "", &["version", "style", "elements"])?;
+
+		let style = self.field(document, "", "style").and_then(|value| self.style(value, "/style"));
+		let elements = self.field(document, "", "elements").and_then(|value| self.array(value, "/elements"))
+			.map(|elements| elements.iter().enumerate().filter_map(|(index, element)| self.element(element, &format!("/elements/{index}"))).collect::<Vec<_>>());
+
+		return Some(Document { version: CURRENT_VERSION, style: style?, elements: elements? });
+	}
+
+	fn style(&mut self, value: &Value, path: &str) -> Option<Style> {
+		let style = self.object(value, path, &["backgroundColor", "foregroundColor"])?;
+
+		let background_color = self.field(style, path, "backgroundColor").and_then(|value| self.css_value(value, &format!("{path}/backgroundColor")));
+		let foreground_color = self.field(style, path, "foregroundColor").and_then(|value| self.css_value(value, &format!("{path}/foregroundColor")));
+
+		return Some(Style { background_color: background_color?, foreground_color: foreground_color? });
+	}
+
+	fn element(&mut self, value: &Value, path: &str) -> Option<Element> {
+		let element = value.as_object();
+		let element_type = element.and_then(|element| element.get("type")).and_then(|value| value.as_str());
+
+		match element_type {
+			Some("Shortcut") => return self.shortcut_container(value, path).map(Element::Shortcut),
+			Some(other) => self.error(&format!("{path}/type"), format!("unknown element type {other:?}")),
+			None if element.is_none() => self.error(path, "expected an object"),
+			None => self.error(&format!("{path}/type"), "expected a string"),
+		}
+
+		return None;
+	}
+
+	fn shortcut_container(&mut self, value: &Value, path: &str) -> Option<ShortcutContainer> {
+		let container = self.object(value, path, &["type", "name", "styles", "content"])?;
+
+		let name = match container.get("name") {
+			Some(value) => self.string(value, &format!("{path}/name"), MAX_TEXT),
+			None => Some(String::new()),
+		};
+		let styles = self.field(container, path, "styles").and_then(|value| self.shortcut_styles(value, &format!("{path}/styles")));
+		let content = self.field(container, path, "content").and_then(|value| self.array(value, &format!("{path}/content")))
+			.map(|content| content.iter().enumerate().filter_map(|(index, shortcut)| self.shortcut(shortcut, &format!("{path}/content/{index}"))).collect::<Vec<_>>());
+
+		return Some(ShortcutContainer { name: name?, styles: styles?, content: content? });
+	}
+
+	fn shortcut_styles(&mut self, value: &Value, path: &str) -> Option<ShortcutStyles> {
+		let styles = self.object(value, path, &["width", "cols", "backgroundColor"])?;
+
+		let width = self.field(styles, path, "width").and_then(|value| self.css_value(value, &format!("{path}/width")));
+		let background_color = self.field(styles, path, "backgroundColor").and_then(|value| self.css_value(value, &format!("{path}/backgroundColor")));
+		let cols = self.field(styles, path, "cols").and_then(|value| match value.as_u64() {
+			Some(cols) if (1..=MAX_COLS).contains(&cols) => Some(cols),
+			_ => {
+				self.error(&format!("{path}/cols"), format!("expected an integer between 1 and {MAX_COLS}"));
+				None
+			}
+		});
+
+		return Some(ShortcutStyles { width: width?, cols: cols?, background_color: background_color? });
+	}
+
+	fn shortcut(&mut self, value: &Value, path: &str) -> Option<Shortcut> {
+		let shortcut = self.object(value, path, &["name", "link", "logo"])?;
+
+		let name = self.field(shortcut, path, "name").and_then(|value| self.string(value, &format!("{path}/name"), MAX_TEXT));
+		let link = self.field(shortcut, path, "link").and_then(|value| self.url(value, &format!("{path}/link")));
+		let logo = self.field(shortcut, path, "logo").and_then(|value| self.url(value, &format!("{path}/logo")));
+
+		return Some(Shortcut { name: name?, link: link?, logo: logo? });
+	}
+
+	// unknown fields are reported, the frontend would silently drop them on the next save
+	fn object<'a>(&mut self, value: &'a Value, path: &str, fields: &[&str]) -> Option<&'a Map<String, Value>> {
+		let Some(object) = value.as_object() else {
+			self.error(path, "expected an object");
+			return None;
+		};
+
+		for key in object.keys().filter(|key| !fields.contains(&key.as_str())) {
+			self.error(&format!("{path}/{}", key.replace("~", "~0").replace("/", "~1")), "unknown field");
+		}
+
+		return Some(object);
+	}
+
+	fn field<'a>(&mut self, object: &'a Map<String, Value>, path: &str, key: &str) -> Option<&'a Value> {
+		let value = object.get(key);
+
+		if value.is_none() {
+			self.error(&format!("{path}/{key}"), "missing field");
+		}
+
+		return value;
+	}
+
+	fn array<'a>(&mut self, value: &'a Value, path: &str) -> Option<&'a Vec<Value>> {
+		let array = value.as_array();
+
+		if array.is_none() {
+			self.error(path, "expected an array");
+		}
+
+		return array;
+	}
+
+	fn string(&mut self, value: &Value, path: &str, max_length: usize) -> Option<String> {
+		match value.as_str() {
+			Some(string) if string.chars().count() <= max_length => return Some(string.to_string()),
+			Some(_) => self.error(path, format!("longer than {max_length} characters")),
+			None => self.error(path, "expected a string"),
+		}
+
+		return None;
+	}
+
+	// values end up in style properties, only colors and lengths are expected
+	fn css_value(&mut self, value: &Value, path: &str) -> Option<String> {
+		let css_value = self.string(value, path, 64)?;
+
+		if css_value.trim().is_empty() || !css_value.chars().all(|c| c.is_ascii_alphanumeric() || "#(),.% -".contains(c)) {
+			self.error(path, format!("{css_value:?} is not a css color or length"));
+			return None;
+		}
+
+		return Some(css_value);
+	}
+
+	// links and logos are inserted as href and src, only http(s), data:image and relative paths can't run scripts
+	fn url(&mut self, value: &Value, path: &str) -> Option<String> {
+		let url = self.string(value, path, MAX_URL)?;
+
+		// browsers drop tabs and newlines from urls, java\tscript: would still run
+		if url.chars().any(|c| c.is_control() || "\"'<>`".contains(c)) {
+			self.error(path, "quotes, angle brackets and control characters are not allowed in links");
+			return None;
+		}
+
+		// relative paths resolve against the page, which is served over http(s)
+		let parsed = Url::parse("https://startpage.invalid/").and_then(|base| base.join(&url));
+		let allowed = parsed.is_ok_and(|parsed| match parsed.scheme() {
+			"http" | "https" => true,
+			"data" => parsed.path().to_ascii_lowercase().starts_with("image/"),
+			_ => false,
+		});
+
+		if !allowed {
+			self.error(path, "only http(s), data:image and relative links are allowed");
+			return None;
+		}
+
+		return Some(url);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn migrates_unversioned_documents() {
+		let content = br##"{"style":{"backgroundColor":"#131319","foregroundColor":"#2d2d38"},"elements":[{"type":"Shortcut","styles":{"width":"100px","cols":"5","backgroundColor":"#2d2d38"},"content":[{"name":"SRF","link":"https://www.srf.ch","logo":"img/srf.svg"}]}]}"##;
+
+		assert!(is_outdated(content));
+
+		let document = Document::parse(content).unwrap_or_else(|_| panic!("document is valid"));
+		let Element::Shortcut(container) = &document.elements[0];
+
+		assert_eq!(document.version, CURRENT_VERSION);
+		assert_eq!(container.styles.cols, 5);
+		assert!(!is_outdated(&document.to_vec()));
+	}
+
+	#[test]
+	fn reports_every_error_with_its_path() {
+		let content = br##"{"version":1,"style":{"backgroundColor":"red;}","foregroundColor":"#fff","font":"x"},"elements":[{"type":"Shortcut","styles":{"width":"100px","cols":0,"backgroundColor":"#000"},"content":[{"name":"x","link":"javascript:alert(1)"}]},{"type":"Clock"}]}"##;
+
+		let Err(errors) = Document::parse(content) else {
+			panic!("document is invalid");
+		};
+
+		let paths: Vec<&str> = errors.iter().map(|error| error.path.as_str()).collect();
+		assert_eq!(paths, vec!["/style/font", "/style/backgroundColor", "/elements/0/styles/cols", "/elements/0/content/0/link", "/elements/0/content/0/logo", "/elements/1/type"]);
+	}
+
+	#[test]
+	fn round_trips_documents_of_the_frontend() {
+		// saved by the edit group dialog before it stored numbers
+		let edited = br##"{"style":{"backgroundColor":"#131319","foregroundColor":"#2d2d38"},"elements":[{"type":"Shortcut","styles":{"width":"120px","cols":"6","backgroundColor":"#2d2d38"},"content":[{"name":"SRF","link":"https://www.srf.ch","logo":""}]}]}"##;
+
+		assert!(is_outdated(edited));
+
+		let document = Document::parse(edited).unwrap_or_else(|_| panic!("document is valid"));
+		let Element::Shortcut(container) = &document.elements[0];
+		assert_eq!(container.styles.cols, 6);
+
+		// the migrated document is what the frontend loads, edits and uploads again
+		let stored = document.to_vec();
+		let reparsed = Document::parse(&stored).unwrap_or_else(|_| panic!("stored document is valid"));
+		assert!(!is_outdated(&stored));
+		assert_eq!(reparsed.to_vec(), stored);
+
+		// a new document with a named group of the current frontend
+		let created = br##"{"version":1,"style":{"backgroundColor":"#131319", "foregroundColor":"#2d2d38"},"elements":[{"type":"Shortcut","name":"News","styles":{"width":"100px","cols":4,"backgroundColor":"#2d2d38"},"content":[]}]}"##;
+		let document = Document::parse(created).unwrap_or_else(|_| panic!("document is valid"));
+		assert_eq!(serde_json::from_slice::<Value>(&document.to_vec()).unwrap(), serde_json::from_slice::<Value>(created).unwrap());
+	}
+
+	#[test]
+	fn allows_only_safe_links() {
+		let mut validator = Validator { errors: vec![] };
+
+		for url in ["https://www.srf.ch/news?a=1&b=2", "http://example.com", "img/srf.svg", "/api/startpage/icon?url=x", "//cdn.example.com/i.png", "data:image/png;base64,iVBO", ""] {
+			assert_eq!(validator.url(&Value::from(url), "/link").as_deref(), Some(url));
+		}
+		assert!(validator.errors.is_empty());
+
+		for url in ["javascript:alert(1)", "java\tscript:alert(1)", " JavaScript:alert(1)", "vbscript:x", "data:text/html,<script>", "file:///etc/passwd", "x\" onerror=\"alert(1)", "img/a'.png", "https://example.com/\n"] {
+			assert_eq!(validator.url(&Value::from(url), "/link"), None, "{url:?} is rejected");
+		}
+	}
+
+	#[test]
+	fn rejects_newer_versions() {
+		let Err(errors) = Document::parse(br#"{"version":99,"style":{},"elements":[]}"#) else {
+			panic!("version is unsupported");
+		};
+
+		assert_eq!(errors[0].path, "/version");
+	}
+}
@@ -0,0 +1,31 @@
+use std::{
+	env, fs, process,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicUsize, Ordering}
+};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// dir below the system temp dir for one test, removed again when it is dropped
+pub struct TempDir {
+	path: PathBuf,
+}
+
+impl TempDir {
+	pub fn new(name: &str) -> TempDir {
+		let path = env::temp_dir().join(format!("backend-{name}-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::SeqCst)));
+		fs::create_dir_all(&path).expect("temp dir can be created");
+
+		return TempDir { path };
+	}
+
+	pub fn path(&self) -> &Path {
+		return &self.path;
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.path);
+	}
+}
@@ -9,17 +9,23 @@ use hex;
 use hmac::{Hmac, Mac};
 use http::{HeaderMap, StatusCode};
 use reqwest;
+use serde::{Deserialize, Serialize};
 use serde_json;
 use sha2::Sha256;
 use subtle::ConstantTimeEq;
-use tokio::{fs, io::AsyncWriteExt};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
 use std::{
 	collections::{HashMap, HashSet},
-	env::var,
-	path::Path
+	future::Future,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicBool, Ordering}
 };
 
-use crate::error;
+use crate::{
+	config,
+	error,
+	http_client::{HttpClient, Upstream}
+};
 
 #[derive(Clone)]
 struct EnvData {
@@ -32,54 +38,63 @@ struct EnvData {
 	branch: String,
 	repo_map: HashMap<String, String>,
 	local_map: HashMap<String, String>,
+	github: Upstream,
+	github_raw: Upstream,
 }
 
-pub async fn router() -> Router {
-	// Generate repo map from env (determine if a file has to be considered)
-	let mut repo_map = HashMap::new();
-	let repo_map_str = var("REPO_MAP").expect("[Workflow] Missing REPO_MAP env var");
+// only one deploy may touch TEMP_DIR/PROD_DIR at a time, shutdown waits for it
+static JOB_LOCK: Mutex<()> = Mutex::const_new(());
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
 
-	for entry in repo_map_str.split("|") {
-		if let Some((key, value)) = entry.split_once(";") {
-			repo_map.insert(key.to_string(), value.to_string());
-		}
-	}
+const ROLLBACK_DIR: &str = ".rollback";
+const JOURNAL_FILE: &str = "journal";
 
-	// Generate folder map from env (determine where the files have to be stored)
-	let mut local_map = HashMap::new();
-	let local_map_str = var("LOCAL_MAP").expect("[Workflow] Missing LOCAL_MAP env var");
-	
-	for entry in local_map_str.split("|") {
-		if let Some((key, value)) = entry.split_once(";") {
-			local_map.insert(key.to_string(), value.to_string());
-		}
-	}
+pub async fn router(http_client: &HttpClient) -> Option<Router> {
+	let mut env = config::RequiredEnv::new("Workflow");
 
-	// fill env_data struct
-	let branch = var("GITHUB_BRANCH").expect("[Workflow] Missing GITHUB_BRANCH env var");
+	let repo_map_str = env.get("REPO_MAP");
+	let local_map_str = env.get("LOCAL_MAP");
+	let branch = env.get("GITHUB_BRANCH");
 
 	let env_data = EnvData {
-		bearer: var("COMPARE_API_BEARER").expect("[Workflow] Missing COMPARE_API_BEARER env var"),
-		secret: var("GITHUB_WEBHOOK_SECRET").expect("[Workflow] Missing GITHUB_WEBHOOK_SECRET env var"),
-		temp_dir: var("TEMP_DIR").expect("[Workflow] Missing TEMP_DIR env var"),
-		prod_dir: var("PROD_DIR").expect("[Workflow] Missing PROD_DIR env var"),
-		github_user_agent: var("GITHUB_USER_AGENT").expect("[Workflow] Missing GITHUB_USER_AGENT env var"),
+		bearer: env.get("COMPARE_API_BEARER"),
+		secret: env.get("GITHUB_WEBHOOK_SECRET"),
+		temp_dir: env.get("TEMP_DIR"),
+		prod_dir: env.get("PROD_DIR"),
+		github_user_agent: env.get("GITHUB_USER_AGENT"),
 		git_ref: format!("refs/heads/{}", branch),
 		branch: branch,
-		repo_map: repo_map,
-		local_map: local_map,
+		// determine if a file has to be considered
+		repo_map: config::parse_map(&repo_map_str),
+		// determine where the files have to be stored
+		local_map: config::parse_map(&local_map_str),
+		github: http_client.upstream("github"),
+		github_raw: http_client.upstream("github_raw"),
 	};
 
+	if !env.is_complete() {
+		return None;
+	}
+
+	if overlapping(Path::new(&env_data.temp_dir), Path::new(&env_data.prod_dir)) {
+		eprintln!("[Workflow] Disabled, TEMP_DIR and PROD_DIR must not contain each other");
+		return None;
+	}
+
+	// undo half applied deploys and clear leftovers of interrupted runs
+	recover(Path::new(&env_data.temp_dir), Path::new(&env_data.prod_dir)).await;
+
 	// do auto refresh from compare after restart when env var is set to true
-	if let Ok(value) = var("AUTO_FETCH") && value.to_ascii_lowercase() == "true" {
-		let _ = refresh_from_compare(&env_data).await;
+	if config::flag("AUTO_FETCH", false) {
+		let job_data = env_data.clone();
+		let _ = spawn_job(async move { refresh_from_compare(&job_data).await }).await;
 	}
 
 	// return router
-	return Router::new()
+	return Some(Router::new()
 		.route("/refresh-from-compare", get(refresh_from_compare_bearer))
 		.route("/refresh-from-webhook", post(refresh_from_webhook))
-		.with_state(env_data);
+		.with_state(env_data));
 }
 
 async fn refresh_from_compare_bearer(State(env_data): State<EnvData>, headers: HeaderMap) -> Result<Response, Response> {
@@ -91,7 +106,7 @@ async fn refresh_from_compare_bearer(State(env_data): State<EnvData>, headers: H
 		.ok_or_else(|| error::generic_unauthorized_error("[Workflow-c2] Bearer required"))?;
 
 	if header_signature.ct_eq(env_data.bearer.as_bytes()).into() {
-		return refresh_from_compare(&env_data).await;
+		return spawn_job(async move { refresh_from_compare(&env_data).await }).await;
 	}
 	else {
 		return Err(error::generic_unauthorized_error("[Workflow-c3] Bearer invalid"));
@@ -99,13 +114,11 @@ async fn refresh_from_compare_bearer(State(env_data): State<EnvData>, headers: H
 }
 
 async fn refresh_from_compare(env_data: &EnvData) -> Result<Response, Response> {
-	let client = reqwest::Client::new();
-
 	for (repo_name, frontend_folder) in &env_data.repo_map {
 		println!("[Worflow-c4] Loading commits from {repo_name}");
 
 		// get latest tag
-		let tag_obj = match fetch_json(format!("https://api.github.com/repos/{repo_name}/tags"), env_data, &client).await {
+		let tag_obj = match fetch_json(format!("https://api.github.com/repos/{repo_name}/tags"), env_data).await {
 			Ok(obj) => obj,
 			Err(e) => {
 				eprintln!("[Workflow-c5-{e}");
@@ -118,7 +131,7 @@ async fn refresh_from_compare(env_data: &EnvData) -> Result<Response, Response>
 		};
 
 		// get changed files
-		let compare_obj = match fetch_json(format!("https://api.github.com/repos/{repo_name}/compare/{tag_name}...{}", env_data.branch), env_data, &client).await {
+		let compare_obj = match fetch_json(format!("https://api.github.com/repos/{repo_name}/compare/{tag_name}...{}", env_data.branch), env_data).await {
 			Ok(obj) => obj,
 			Err(e) => {
 				eprintln!("[Workflow-c7-{e}");
@@ -164,8 +177,8 @@ async fn refresh_from_compare(env_data: &EnvData) -> Result<Response, Response>
 				};
 
 				match file["status"].as_str() {
-					Some(status) if status == "added" => added_files.insert(filename.to_string()),
-					Some(status) if status == "removed" => removed_files.insert(filename.to_string()),
+					Some("added") => added_files.insert(filename.to_string()),
+					Some("removed") => removed_files.insert(filename.to_string()),
 					Some(_) => modified_files.insert(filename.to_string()),
 					None => continue,
 				};
@@ -178,8 +191,8 @@ async fn refresh_from_compare(env_data: &EnvData) -> Result<Response, Response>
 	return Ok((StatusCode::OK).into_response());
 }
 
-async fn fetch_json(url: String, env_data: &EnvData, client: &reqwest::Client) -> Result<serde_json::Value, String> {
-	let response = client.request(reqwest::Method::GET, url)
+async fn fetch_json(url: String, env_data: &EnvData) -> Result<serde_json::Value, String> {
+	let response = env_data.github.get(url)
 		.header(reqwest::header::ACCEPT, "application/vnd.github+json")
 		.header(reqwest::header::USER_AGENT, &env_data.github_user_agent)
 		.header("X-GitHub-Api-Version", "2022-11-28")
@@ -250,7 +263,12 @@ async fn refresh_from_webhook(State(env_data): State<EnvData>, headers: HeaderMa
 		create_hashset(commit, "removed", &mut removed_files, frontend_folder);
 	}
 
-	return download_files(&env_data, modified_files, added_files, removed_files, repo_name, frontend_folder).await;
+	let repo_name = repo_name.to_string();
+	let frontend_folder = frontend_folder.to_string();
+
+	return spawn_job(async move {
+		download_files(&env_data, modified_files, added_files, removed_files, &repo_name, &frontend_folder).await
+	}).await;
 }
 
 fn create_hashset(commit: &serde_json::Value, key: &str, hashset: &mut HashSet<String>, frontend_folder: &str) {
@@ -272,19 +290,20 @@ async fn download_files(
 	env_data: &EnvData,
 	modified_files: HashSet<String>,
 	mut added_files: HashSet<String>,
-	mut removed_files: HashSet<String>,
+	removed_files: HashSet<String>,
 	repo_name: &str,
 	frontend_folder: &str
 ) -> Result<Response, Response> {
 	// download new and changed files
 	let temp_dir = Path::new(&env_data.temp_dir);
-	let client = reqwest::Client::new();
 	let repo_url = format!("https://raw.githubusercontent.com/{repo_name}/{}/", env_data.branch);
 
-	added_files.extend(modified_files.iter().cloned());
+	added_files.extend(modified_files);
+	// only these files are changed in prod, a failed download keeps the prod copy
+	let mut downloaded = HashSet::new();
 
-	'file_loop: for file in &added_files {
-		let mut stream = match client.get(format!("{repo_url}{file}")).send().await {
+	'file_loop: for file in added_files {
+		let mut stream = match env_data.github_raw.get(format!("{repo_url}{file}")).send().await {
 			Ok(res) if res.status().is_success() => res,
 			Ok(_) => {
 				eprintln!("[Workflow-d1] Could not donwload {file}");
@@ -335,6 +354,8 @@ async fn download_files(
 				}
 			};
 		}
+
+		downloaded.insert(file);
 	}
 
 
@@ -345,12 +366,14 @@ async fn download_files(
 		None => return Err(error::generic_request_error("[Workflow-d7] Repository is not in local map")),
 	};
 
-	removed_files.extend(modified_files);
+	// every change to prod is journaled so an interrupted deploy can be rolled back on the next start
+	let mut journal = Journal::open(temp_dir).await
+		.map_err(|e| error::generic_internal_error(&format!("[Workflow-d12] Could not open rollback journal {e}")))?;
 
 	for file in removed_files {
-		let prod_path = prod_dir.join(&file.replace(frontend_folder, ""));
+		let prod_path = prod_dir.join(file.replace(frontend_folder, ""));
 
-		match fs::remove_file(&prod_path).await {
+		match journal.backup(&prod_path).await {
 			Ok(_) => count_removed += 1,
 			Err(e) => eprintln!("[Workflow-d8] {file} {e}"),
 		}
@@ -359,28 +382,372 @@ async fn download_files(
 	// move temp to prod
 	let mut count_added = 0;
 
-	for file in added_files {
+	// modified files are backed up by the journal when they are replaced
+	for file in downloaded {
 		let temp_path = temp_dir.join(&file);
-		let prod_path = prod_dir.join(&file.replace(frontend_folder, ""));
+		let prod_path = prod_dir.join(file.replace(frontend_folder, ""));
 
 		// create parent folders
 		let parent_folder = prod_path.parent().unwrap_or(&prod_dir);
 		match fs::create_dir_all(parent_folder).await {
-			Ok(_) => count_added += 1,
+			Ok(_) => (),
 			Err(e) => {
 				eprintln!("[Workflow-d9] {file} {e}");
 				continue;
 			}
 		};
 
-		// move file
-		match fs::rename(&temp_path, &prod_path).await {
-			Ok(_) => (),
-			Err(e) => eprintln!("[Workflow-d10] {file} {e}"),
+		// move file, a half replaced file would lose its backup with finish
+		match journal.add(&temp_path, &prod_path).await {
+			Ok(_) => count_added += 1,
+			Err(e) => {
+				let count_restored = journal.abort().await;
+				return Err(error::generic_internal_error(&format!("[Workflow-d10] {file} {e}, rolled back the update and restored {count_restored} files")));
+			}
 		}
 	}
 
+	journal.finish().await;
+
 	println!("[Workflow-d11] Finished update with {count_added} added/modified and {count_removed} removed files");
 
 	return Ok((StatusCode::OK).into_response());
-}
\ No newline at end of file
+}
+
+// run deploys in their own task so a dropped connection can't stop them halfway
+async fn spawn_job<F>(job: F) -> Result<Response, Response>
+where
+	F: Future<Output = Result<Response, Response>> + Send + 'static
+{
+	if SHUTTING_DOWN.load(Ordering::SeqCst) {
+		return Err(error::generic_unavailable_error("[Workflow-j1] Server is shutting down"));
+	}
+
+	let handle = tokio::spawn(async move {
+		let _guard = JOB_LOCK.lock().await;
+
+		if SHUTTING_DOWN.load(Ordering::SeqCst) {
+			return Err(error::generic_unavailable_error("[Workflow-j2] Server is shutting down"));
+		}
+
+		job.await
+	});
+
+	return handle.await.map_err(|e| error::generic_internal_error(&format!("[Workflow-j3] {e}")))?;
+}
+
+// refuse new deploys and wait until the running one is finished
+pub async fn wait_for_jobs() {
+	SHUTTING_DOWN.store(true, Ordering::SeqCst);
+	let _guard = JOB_LOCK.lock().await;
+}
+
+// roll back a deploy that was interrupted while changing prod and clear TEMP_DIR
+async fn recover(temp_dir: &Path, prod_dir: &Path) {
+	let journal_path = temp_dir.join(ROLLBACK_DIR).join(JOURNAL_FILE);
+
+	if let Ok(journal) = fs::read_to_string(&journal_path).await {
+		eprintln!("[Workflow-r1] Found interrupted deploy, rolling back");
+
+		let count_restored = roll_back(&journal).await;
+
+		println!("[Workflow-r4] Rolled back interrupted deploy, restored {count_restored} files");
+	}
+
+	if overlapping(temp_dir, prod_dir) {
+		eprintln!("[Workflow-r5] TEMP_DIR and PROD_DIR contain each other, not cleaning TEMP_DIR");
+		return;
+	}
+
+	match fs::remove_dir_all(temp_dir).await {
+		Ok(_) => println!("[Workflow-r6] Cleaned {}", temp_dir.display()),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+		Err(e) => eprintln!("[Workflow-r7] {} {e}", temp_dir.display()),
+	}
+}
+
+// undo the changes of a journal, returns the number of restored files
+async fn roll_back(journal: &str) -> usize {
+	// a line cut off by the interruption belongs to a change which never happened
+	let entries: Vec<JournalEntry> = journal.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+	let mut count_restored = 0;
+
+	// remove files which were moved into prod
+	for entry in &entries {
+		let JournalEntry::Added { prod } = entry else {
+			continue;
+		};
+
+		match fs::remove_file(prod).await {
+			Ok(_) => (),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+			Err(e) => eprintln!("[Workflow-r2] {} {e}", prod.display()),
+		}
+	}
+
+	// move replaced and removed files back
+	for entry in &entries {
+		let JournalEntry::Backup { backup, prod } = entry else {
+			continue;
+		};
+
+		if let Some(parent_folder) = prod.parent() {
+			fs::create_dir_all(parent_folder).await.unwrap_or_default();
+		}
+
+		match fs::rename(backup, prod).await {
+			Ok(_) => count_restored += 1,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+			Err(e) => eprintln!("[Workflow-r3] {} {e}", prod.display()),
+		}
+	}
+
+	return count_restored;
+}
+
+// TEMP_DIR is wiped on start, so it must neither be PROD_DIR nor inside or around it
+fn overlapping(temp_dir: &Path, prod_dir: &Path) -> bool {
+	let temp_dir = normalize(temp_dir);
+	let prod_dir = normalize(prod_dir);
+
+	return temp_dir.starts_with(&prod_dir) || prod_dir.starts_with(&temp_dir);
+}
+
+// absolute path with symlinks resolved where it exists and . and .. removed
+fn normalize(path: &Path) -> PathBuf {
+	if let Ok(path) = std::fs::canonicalize(path) {
+		return path;
+	}
+
+	let absolute = std::path::absolute(path).unwrap_or(path.to_path_buf());
+	let mut normalized = PathBuf::new();
+
+	for component in absolute.components() {
+		match component {
+			std::path::Component::CurDir => (),
+			std::path::Component::ParentDir => {
+				normalized.pop();
+			},
+			component => normalized.push(component),
+		}
+	}
+
+	return normalized;
+}
+
+// one json object per line, paths may contain tabs and newlines
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum JournalEntry {
+	Backup { backup: PathBuf, prod: PathBuf },
+	Added { prod: PathBuf },
+}
+
+struct Journal {
+	dir: PathBuf,
+	file: fs::File,
+	count: usize,
+}
+
+impl Journal {
+	async fn open(temp_dir: &Path) -> std::io::Result<Journal> {
+		let dir = temp_dir.join(ROLLBACK_DIR);
+		fs::create_dir_all(&dir).await?;
+
+		let file = fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(dir.join(JOURNAL_FILE))
+			.await?;
+
+		return Ok(Journal { dir, file, count: 0 });
+	}
+
+	async fn write(&mut self, entry: &JournalEntry) -> std::io::Result<()> {
+		let mut line = serde_json::to_vec(entry)?;
+		line.push(b'\n');
+
+		self.file.write_all(&line).await?;
+		return self.file.sync_data().await;
+	}
+
+	// move a prod file out of the way instead of deleting it
+	async fn backup(&mut self, prod_path: &Path) -> std::io::Result<()> {
+		let backup_path = self.dir.join(self.count.to_string());
+		self.count += 1;
+
+		self.write(&JournalEntry::Backup { backup: backup_path.clone(), prod: prod_path.to_path_buf() }).await?;
+		return fs::rename(prod_path, &backup_path).await;
+	}
+
+	// a file which is replaced is backed up first so the rollback can restore it
+	async fn add(&mut self, temp_path: &Path, prod_path: &Path) -> std::io::Result<()> {
+		// nothing is backed up for a file which can't replace it
+		fs::metadata(temp_path).await?;
+
+		if fs::try_exists(prod_path).await? {
+			self.backup(prod_path).await?;
+		}
+
+		self.write(&JournalEntry::Added { prod: prod_path.to_path_buf() }).await?;
+		return fs::rename(temp_path, prod_path).await;
+	}
+
+	// undo the deploy so far, returns the number of restored files
+	async fn abort(self) -> usize {
+		drop(self.file);
+
+		let journal = fs::read_to_string(self.dir.join(JOURNAL_FILE)).await.unwrap_or_default();
+		let count_restored = roll_back(&journal).await;
+
+		match fs::remove_dir_all(&self.dir).await {
+			Ok(_) => (),
+			Err(e) => eprintln!("[Workflow-d13] Could not remove rollback journal {e}"),
+		}
+
+		return count_restored;
+	}
+
+	// deploy is complete, backups are not needed anymore
+	async fn finish(self) {
+		drop(self.file);
+
+		match fs::remove_dir_all(&self.dir).await {
+			Ok(_) => (),
+			Err(e) => eprintln!("[Workflow-d13] Could not remove rollback journal {e}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::temp_dir::TempDir;
+
+	async fn read(path: &Path) -> Option<String> {
+		return fs::read_to_string(path).await.ok();
+	}
+
+	#[tokio::test]
+	async fn rolls_back_interrupted_deploys() {
+		let root = TempDir::new("workflow");
+		let temp_dir = root.path().join("temp");
+		let prod_dir = root.path().join("prod");
+		fs::create_dir_all(&temp_dir).await.unwrap();
+		fs::create_dir_all(&prod_dir).await.unwrap();
+
+		// tabs and newlines broke the old line format
+		let removed = prod_dir.join("removed\tfile.txt");
+		let replaced = prod_dir.join("replaced\nfile.txt");
+		let added = prod_dir.join("new/added.txt");
+		fs::write(&removed, "removed").await.unwrap();
+		fs::write(&replaced, "old").await.unwrap();
+		fs::write(temp_dir.join("replaced"), "new").await.unwrap();
+		fs::write(temp_dir.join("added"), "added").await.unwrap();
+
+		let mut journal = Journal::open(&temp_dir).await.unwrap();
+		journal.backup(&removed).await.unwrap();
+		journal.add(&temp_dir.join("replaced"), &replaced).await.unwrap();
+		fs::create_dir_all(added.parent().unwrap()).await.unwrap();
+		journal.add(&temp_dir.join("added"), &added).await.unwrap();
+		// interrupted before finish
+		drop(journal);
+
+		assert_eq!(read(&removed).await, None);
+		assert_eq!(read(&replaced).await.as_deref(), Some("new"));
+
+		let journal = read(&temp_dir.join(ROLLBACK_DIR).join(JOURNAL_FILE)).await.unwrap();
+		assert_eq!(journal.lines().count(), 4);
+		assert!(journal.lines().all(|line| serde_json::from_str::<JournalEntry>(line).is_ok()));
+
+		recover(&temp_dir, &prod_dir).await;
+
+		assert_eq!(read(&removed).await.as_deref(), Some("removed"));
+		assert_eq!(read(&replaced).await.as_deref(), Some("old"));
+		assert_eq!(read(&added).await, None);
+		assert!(!fs::try_exists(&temp_dir).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn keeps_prod_files_without_replacement() {
+		let root = TempDir::new("workflow");
+		let temp_dir = root.path().join("temp");
+		let prod_dir = root.path().join("prod");
+		fs::create_dir_all(&temp_dir).await.unwrap();
+		fs::create_dir_all(&prod_dir).await.unwrap();
+
+		let kept = prod_dir.join("kept.txt");
+		fs::write(&kept, "old").await.unwrap();
+
+		// the download of the modified file failed
+		let mut journal = Journal::open(&temp_dir).await.unwrap();
+		assert!(journal.add(&temp_dir.join("kept.txt"), &kept).await.is_err());
+		journal.finish().await;
+
+		assert_eq!(read(&kept).await.as_deref(), Some("old"));
+	}
+
+	#[tokio::test]
+	async fn aborts_deploys() {
+		let root = TempDir::new("workflow");
+		let temp_dir = root.path().join("temp");
+		let prod_dir = root.path().join("prod");
+		fs::create_dir_all(&temp_dir).await.unwrap();
+		fs::create_dir_all(&prod_dir).await.unwrap();
+
+		let removed = prod_dir.join("removed.txt");
+		let replaced = prod_dir.join("replaced.txt");
+		fs::write(&removed, "removed").await.unwrap();
+		fs::write(&replaced, "old").await.unwrap();
+		fs::write(temp_dir.join("replaced"), "new").await.unwrap();
+
+		let mut journal = Journal::open(&temp_dir).await.unwrap();
+		journal.backup(&removed).await.unwrap();
+		journal.add(&temp_dir.join("replaced"), &replaced).await.unwrap();
+
+		assert_eq!(journal.abort().await, 2);
+		assert_eq!(read(&removed).await.as_deref(), Some("removed"));
+		assert_eq!(read(&replaced).await.as_deref(), Some("old"));
+		assert!(!fs::try_exists(temp_dir.join(ROLLBACK_DIR)).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn ignores_truncated_journal_lines() {
+		let root = TempDir::new("workflow");
+		let temp_dir = root.path().join("temp");
+		let prod_dir = root.path().join("prod");
+		fs::create_dir_all(temp_dir.join(ROLLBACK_DIR)).await.unwrap();
+		fs::create_dir_all(&prod_dir).await.unwrap();
+		fs::write(prod_dir.join("kept.txt"), "kept").await.unwrap();
+
+		let entry = serde_json::to_string(&JournalEntry::Added { prod: prod_dir.join("kept.txt") }).unwrap();
+		fs::write(temp_dir.join(ROLLBACK_DIR).join(JOURNAL_FILE), &entry[..entry.len() - 2]).await.unwrap();
+
+		recover(&temp_dir, &prod_dir).await;
+
+		assert_eq!(read(&prod_dir.join("kept.txt")).await.as_deref(), Some("kept"));
+		assert!(!fs::try_exists(&temp_dir).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn keeps_overlapping_dirs() {
+		let root = TempDir::new("workflow");
+		let prod_dir = root.path().join("prod");
+		let temp_dir = prod_dir.join("temp");
+		fs::create_dir_all(&temp_dir).await.unwrap();
+		fs::write(prod_dir.join("index.html"), "prod").await.unwrap();
+
+		assert!(overlapping(&temp_dir, &prod_dir));
+		assert!(overlapping(&prod_dir, &temp_dir));
+		assert!(overlapping(&prod_dir.join("../prod/"), &prod_dir));
+		assert!(overlapping(&root.path().join("missing/./temp/.."), &root.path().join("missing/prod")));
+		assert!(!overlapping(&root.path().join("temp"), &prod_dir));
+		assert!(!overlapping(&root.path().join("prod-temp"), &prod_dir));
+
+		recover(&prod_dir, &temp_dir).await;
+		recover(&temp_dir, &prod_dir).await;
+
+		assert_eq!(read(&prod_dir.join("index.html")).await.as_deref(), Some("prod"));
+		assert!(fs::try_exists(&temp_dir).await.unwrap());
+	}
+}
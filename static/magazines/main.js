@@ -18,6 +18,17 @@ function load() {
 		current_year--;
 	}
 
+	// links from the feed select an edition
+	var params = new URLSearchParams(location.search);
+	if (params.has("year") && params.has("week")) {
+		getWeeks(params.get("year"));
+		el_year.value = params.get("year");
+		el_week.value = params.get("week");
+
+		if (params.has("provider")) loadMagazine(params.get("provider"));
+		return;
+	}
+
 	// get last selected
 	var selected_storage = window.localStorage.getItem("selected_week");
 	if (selected_storage != null) {
@@ -58,90 +69,7 @@ function getWeeks(year) {
 	}
 }
 
-async function loadCoop() {
-	var year = el_year.value;
-	var week = el_week.value;
-	var fetch_amount = 5;
-
-	if (year == "" || week == "") return;
-
-	el_main.innerHTML = "";
-	window.localStorage.setItem("selected_week", `${year}-${week}`);
-
-	// get publication date
-	var date = new Date(year, 0, 4);
-	date.setDate(date.getDate() - (date.getDay() || 7) + 1); // go to monday of first week
-	date.setDate(date.getDate() + (week - 1) *7 + fetch_amount * 4); // monday of selected week + half of fetched amount
-
-	var publication = await findDate(date, week, fetch_amount, 0);
-	if (publication == undefined) return;
-
-	// fetch  and show images
-	var magazin_load = await fetch("/api/magazines/pages", {
-		method: "POST",
-		body: `{"date":"${publication}"}`,
-	});
-
-	if (magazin_load.status != 200) {
-		console.error(magazin_load);
-		return;
-	}
-
-	var magazin = await magazin_load.json();
-
-	for (var i = 0; i < magazin.length; i++) {
-		var element = document.createElement('img');
-		element.src = magazin[i];
-		el_main.appendChild(element);
-	}
-}
-
-async function findDate(date, week, fetch_amount, loop_fix) {
-	loop_fix += 1;
-	// fetch data
-	var fetch_date = new Date(date);
-
-	var month = (fetch_date.getMonth() + 1).toString().padStart(2, "0");
-	var day = fetch_date.getDate().toString().padStart(2, "0");
-
-	var publications_load = await fetch("/api/magazines/publications", {
-		method: "POST",
-		body: `{"date":"${fetch_date.getFullYear()}-${month}-${day}", "amount":${fetch_amount}}`,
-	});
-
-	if (publications_load.status != 200) {
-		console.error(publications_load);
-		return;
-	}
-
-	var pub = await publications_load.json();
-
-	// check if seeked week exists
-	var needs_newer = undefined;
-	var publication = undefined
-
-	for (var i = 0; i < pub.length; i++) {
-		if (pub[i].edition_number == week) {
-			publication = pub[i].publication_date;
-			break;
-		}
-		else if (pub[i].edition_number < week) needs_newer = true;
-		else needs_newer = false;
-	}
-
-	console.log("Loop fix: " + loop_fix)
-
-	// return if found
-	if (publication != undefined) return publication;
-	// prevent to much requests
-	else if (loop_fix >= 3) return;
-	// fetch again if not, dependend if it needs to be newer or not
-	else if (needs_newer) findDate(fetch_date.setDate(fetch_date.getDate() + fetch_amount * 7), week, fetch_amount, loop_fix);
-	else findDate(fetch_date.setDate(fetch_date.getDate() - fetch_amount * 7), week, fetch_amount, loop_fix);
-}
-
-
-async function loadMigros() {
+async function loadMagazine(provider) {
 	var year = el_year.value;
 	var week = el_week.value;
 
@@ -150,9 +78,8 @@ async function loadMigros() {
 	el_main.innerHTML = "";
 	window.localStorage.setItem("selected_week", `${year}-${week}`);
 
-	// List with links to images of all pages: https://reader3.isu.pub/m-magazin/migros-magazin-45-2024-d-os/reader3_4.json
-	// New link since 46-2025 https://publication.issuu.com/m-magazin/migros-magazin-46-2025-d-os/reader4.json
-	var magazin_load = await fetch(`https://publication.issuu.com/m-magazin/migros-magazin-${week.padStart(2, "0")}-${year}-d-os/reader4.json`);
+	// the server resolves the week to its edition
+	var magazin_load = await fetch(`/api/magazines/${provider}/${year}/${week}`);
 
 	if (magazin_load.status != 200) {
 		console.error(magazin_load);
@@ -161,9 +88,9 @@ async function loadMigros() {
 
 	var magazin = await magazin_load.json();
 
-	for (var i = 0; i < magazin.document.pages.length; i++) {
+	for (var i = 0; i < magazin.pages.length; i++) {
 		var element = document.createElement('img');
-		element.src = magazin.document.pages[i].svgUrl;
+		element.src = magazin.pages[i];
 		el_main.appendChild(element);
 	}
 }
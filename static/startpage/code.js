@@ -9,7 +9,7 @@ const classMap = {
 function loadData() {
 	var json = window.localStorage.getItem(location.pathname);
 	if (json == null) {
-		json = '{"style":{"backgroundColor":"#131319", "foregroundColor":"#2d2d38"},"elements":[]}';
+		json = '{"version":1,"style":{"backgroundColor":"#131319", "foregroundColor":"#2d2d38"},"elements":[]}';
 		startEdit();
 	};
 
@@ -234,18 +234,39 @@ function showDialog(closedby) {
 // import
 var import_data = document.createElement('input');
 import_data.type = 'file';
-import_data.accept = '.json';
+import_data.accept = '.json,.html,.htm';
 
 import_data.onchange = e => { 
 	var reader = new FileReader();
 	reader.readAsText(e.target.files[0],'UTF-8');
 
-	reader.onload = readerEvent => {
-		window.localStorage.setItem(location.pathname, readerEvent.target.result);
+	reader.onload = async readerEvent => {
+		var content = readerEvent.target.result;
+
+		// own exports are used as they are, browser bookmark exports are converted by the backend
+		if (!isStartpageData(content)) {
+			var response = await fetch("/api/startpage/import", {method:"POST", body:content});
+			if (!response.ok) {
+				alert("Import failed: " + await response.text());
+				return;
+			}
+			content = await response.text();
+		}
+
+		window.localStorage.setItem(location.pathname, content);
 		document.location.reload();
 	}
 }
 
+function isStartpageData(content) {
+	try {
+		return Array.isArray(JSON.parse(content).elements);
+	}
+	catch {
+		return false;
+	}
+}
+
 function exportData(name) {
 	var link = document.createElement('a');
 	link.download = name + ".json";
@@ -76,6 +76,16 @@ class Shortcut {
 			margin: 10px auto;
 		}
 
+		.shortcut_title {
+			color: white;
+			margin: 0;
+			padding: 20px 20px 0;
+		}
+
+		.shortcut_title:empty {
+			display: none;
+		}
+
 		.shortcut_create {
 			display: none;
 			height: 100%;
@@ -137,7 +147,13 @@ class Shortcut {
 		wrapper.classList.add("startpage_wrapper");
 		wrapper.style.setProperty("background-color", element.styles.backgroundColor);
 
+		// imported groups carry the name of their bookmark folder
+		var title = document.createElement("p");
+		title.classList.add("shortcut_title");
+		title.textContent = element.name || "";
+
 		wrapper.append(button_box);
+		wrapper.append(title);
 		wrapper.append(container);
 
 		return container;
@@ -146,8 +162,17 @@ class Shortcut {
 	static loadElement(link_data) {
 		var link = document.createElement("a");
 		link.classList.add("shortcut_link");
-		link.href = link_data.link;
-		link.innerHTML = `<img src="${link_data.logo}"><p>${link_data.name}</p>`;
+		link.setAttribute("href", link_data.link);
+
+		// shortcuts without a logo use the icon of the linked site
+		var logo = document.createElement("img");
+		logo.setAttribute("src", link_data.logo || "/api/startpage/icon?url=" + encodeURIComponent(link_data.link));
+
+		// names and links are user data, they are never parsed as html
+		var name = document.createElement("p");
+		name.textContent = link_data.name;
+
+		link.append(logo, name);
 
 		if (edit_mode_active) this.editElement(link);
 		return link;
@@ -160,14 +185,17 @@ class Shortcut {
 
 		var width = container.style.getPropertyValue("--width");
 		var cols = container.style.getPropertyValue("--cols");
+		var title = container.parentElement.querySelector(".shortcut_title");
 
 		var html = `
 			<p>Edit Group</p>
+			<label class="shortcut_label">Group name: <input type="text" id="shortcut_group_name"></label>
 			<label class="shortcut_label">Shortcut width: <input type="text" id="shortcut_width" value="${width}"></label>
 			<label class="shortcut_label">Columns amount: <input type="text" id="shortcut_cols" value="${cols}"></label>
 		`;
 		var div = document.createElement("div");
 		div.innerHTML = html;
+		div.querySelector("#shortcut_group_name").value = title.textContent;
 
 		var button = document.createElement("button");
 		button.innerHTML = "Update";
@@ -176,12 +204,18 @@ class Shortcut {
 			var {wrapper_index} = getPosition(container);
 			var container_obj = data.elements[wrapper_index];
 
+			var name = document.getElementById("shortcut_group_name").value.trim();
+			if (name) container_obj.name = name;
+			else delete container_obj.name;
+
 			container_obj.styles.width = document.getElementById("shortcut_width").value;
-			container_obj.styles.cols = document.getElementById("shortcut_cols").value;
+			// the backend only accepts the column count as number
+			container_obj.styles.cols = parseInt(document.getElementById("shortcut_cols").value) || container_obj.styles.cols;
 
 			window.localStorage.setItem(location.pathname, JSON.stringify(data));
 
 			// show input
+			title.textContent = name;
 			container.style.setProperty("--width", container_obj.styles.width);
 			container.style.setProperty("--cols", container_obj.styles.cols);
 
@@ -229,12 +263,15 @@ class Shortcut {
 		// create html
 		var html = `
 			<p>${html_title}</p>
-			<label class="shortcut_label">Name: <input type="text" id="shortcut_name" value="${element_obj.name}"></label>
-			<label class="shortcut_label">URL: <input type="text" id="shortcut_url" value="${element_obj.link}"></label>
-			<label class="shortcut_label">Image: <input type="text" id="shortcut_image" value="${element_obj.logo}"></label>
+			<label class="shortcut_label">Name: <input type="text" id="shortcut_name"></label>
+			<label class="shortcut_label">URL: <input type="text" id="shortcut_url"></label>
+			<label class="shortcut_label">Image: <input type="text" id="shortcut_image"></label>
 		`;
 		var div = document.createElement("div");
 		div.innerHTML = html;
+		div.querySelector("#shortcut_name").value = element_obj.name;
+		div.querySelector("#shortcut_url").value = element_obj.link;
+		div.querySelector("#shortcut_image").value = element_obj.logo;
 
 		var button = document.createElement("button");
 		button.innerHTML = html_button;